@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+
+use tokio_util::task::TaskTracker;
+
+use crate::{authority, crawldb::PageMeta, crawler, devdocs, error::AppError, AppState};
+
+/// Status of a background crawl
+#[derive(Serialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CrawlStatus {
+    Running,
+    Done { pages: usize },
+    Failed { error: String },
+}
+
+#[derive(Serialize, Clone)]
+pub struct CrawlJob {
+    id: u64,
+    /// Filter the crawl targets were limited to
+    target: Option<String>,
+    /// When the crawl was started, in seconds since the Unix epoch
+    started: u64,
+    #[serde(flatten)]
+    status: CrawlStatus,
+}
+
+/// Background crawls started through the admin API
+#[derive(Clone, Default)]
+pub struct CrawlJobs {
+    next_id: Arc<AtomicU64>,
+    jobs: Arc<Mutex<HashMap<u64, CrawlJob>>>,
+    tasks: TaskTracker,
+}
+impl CrawlJobs {
+    /// Wait for running crawls to stop (after shutdown has been triggered)
+    pub async fn wait(&self) {
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    fn get(&self, id: u64) -> Option<CrawlJob> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+    fn set_status(&self, id: u64, status: CrawlStatus) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.status = status;
+        }
+    }
+
+    /// Start crawling in the background, limited to targets matching `target` if given
+    ///
+    /// Crawls share the index writer, so this returns [None] if one is already running.
+    pub fn start(&self, st: AppState, target: Option<String>) -> Option<CrawlJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs
+            .values()
+            .any(|job| matches!(job.status, CrawlStatus::Running))
+        {
+            return None;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let job = CrawlJob {
+            id,
+            target: target.clone(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            status: CrawlStatus::Running,
+        };
+        jobs.insert(id, job.clone());
+        drop(jobs);
+
+        let jobs = self.clone();
+        self.tasks.spawn(async move {
+            let config = st.config();
+            let targets: Vec<_> = config
+                .targets
+                .iter()
+                .filter(|t| target.as_ref().map_or(true, |f| t.matches(f)))
+                .cloned()
+                .collect();
+
+            let bundles: Vec<_> = config
+                .devdocs
+                .iter()
+                .filter(|b| target.as_ref().map_or(true, |f| b.matches(f)))
+                .collect();
+
+            info!(
+                "crawl {id}: crawling {} targets and {} DevDocs bundles",
+                targets.len(),
+                bundles.len()
+            );
+
+            let res = async {
+                let mut stats = crawler::crawl_targets(
+                    &targets,
+                    &config,
+                    &st.extractors,
+                    &st.se,
+                    &st.index,
+                    &st.crawldb,
+                    &st.shutdown,
+                )
+                .await?;
+
+                for bundle in bundles {
+                    if st.shutdown.is_cancelled() {
+                        break;
+                    }
+                    info!(slug = %bundle.slug, "indexing DevDocs bundle");
+                    let ct = devdocs::import(
+                        bundle,
+                        &config,
+                        &st.extractors,
+                        &st.se,
+                        &st.index,
+                        &st.crawldb,
+                        &st.shutdown,
+                    )
+                    .await?;
+                    info!(slug = %bundle.slug, docs = ct, "finished indexing DevDocs bundle");
+                    *stats.sources.entry(bundle.source()).or_default() += ct;
+                }
+
+                Ok::<_, Box<dyn Error>>(stats)
+            }
+            .await
+            .map_err(|err| err.to_string());
+
+            let status = match res {
+                Ok(stats) => {
+                    let pages = stats.total();
+                    info!("crawl {id}: indexed {pages} pages");
+                    // The crawl changed the link graph
+                    authority::refresh(&st).await;
+                    CrawlStatus::Done { pages }
+                }
+                Err(error) => {
+                    error!("crawl {id} failed: {error}");
+                    CrawlStatus::Failed { error }
+                }
+            };
+            jobs.set_status(id, status);
+        });
+
+        Some(job)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CrawlParams {
+    /// Only crawl targets from this source, or whose start URL contains this
+    pub target: Option<String>,
+}
+
+/// `POST /admin/crawl`: start a crawl in the background
+pub async fn start_crawl(
+    State(st): State<AppState>,
+    Query(params): Query<CrawlParams>,
+) -> Result<(StatusCode, Json<CrawlJob>), AppError> {
+    match st.jobs.start(st.clone(), params.target) {
+        Some(job) => Ok((StatusCode::ACCEPTED, Json(job))),
+        None => Err(AppError::new(
+            StatusCode::CONFLICT,
+            "a crawl is already running",
+        )),
+    }
+}
+
+/// `GET /admin/crawl/:id`: get the status of a crawl
+pub async fn crawl_status(
+    State(st): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<CrawlJob>, AppError> {
+    st.jobs.get(id).map(Json).ok_or_else(AppError::not_found)
+}
+
+#[derive(Deserialize)]
+pub struct PageParams {
+    url: String,
+}
+
+/// `GET /admin/page?url=...`: get what's known about fetching a URL
+pub async fn page_meta(
+    State(st): State<AppState>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<PageMeta>, AppError> {
+    st.crawldb
+        .get(&params.url)?
+        .map(Json)
+        .ok_or_else(AppError::not_found)
+}
+
+/// `POST /admin/reload`: reload the config file
+pub async fn reload_config(State(st): State<AppState>) -> Result<StatusCode, AppError> {
+    st.reload_config().map_err(|err| {
+        AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("couldn't reload config: {err}"),
+        )
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
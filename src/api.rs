@@ -0,0 +1,168 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    error::AppError,
+    ingest, preview, ratelimit,
+    search::{self, SearchOptions, SearchRes, DEFAULT_LANG},
+    suggest, AppState, SearchParams,
+};
+
+/// OpenAPI description of the HTTP API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Mini Search"),
+    paths(
+        search,
+        search_batch,
+        suggest,
+        preview::preview,
+        ingest::index_url,
+        ingest::index_documents,
+        ingest::delete_document,
+    ),
+    components(schemas(
+        SearchRes,
+        BatchSearchReq,
+        preview::Preview,
+        ingest::IndexReq,
+        ingest::IndexedPage,
+        ingest::DocumentReq,
+        ingest::DocumentsRes,
+    ))
+)]
+pub struct ApiDoc;
+
+/// `GET /api/openapi.json`: the OpenAPI spec for the HTTP API
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// `GET /api/search`: search, returning the same results as the search page
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Search results", body = SearchRes),
+        (status = 400, description = "No query was given"),
+    ),
+)]
+pub async fn search(
+    State(st): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchRes>, AppError> {
+    let opts = params.options();
+    let Some(q) = params.query else {
+        return Err(AppError::bad_request("missing query (`q`)"));
+    };
+
+    Ok(Json(search::search(&st, q, opts).await?))
+}
+
+/// Most queries accepted in a single batch
+const MAX_BATCH_QUERIES: usize = 32;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchSearchReq {
+    queries: Vec<String>,
+    /// Include a ranking explanation for each result
+    #[serde(default)]
+    explain: bool,
+    /// Include how long each stage of each search took
+    #[serde(default)]
+    debug: bool,
+    /// Language to limit results to (`all` for every language)
+    lang: Option<String>,
+    /// Only include pages crawled on or after a day (like `2024-06-30`), or a shortcut like
+    /// `past_year`
+    after: Option<String>,
+    /// Only include pages crawled before a day (like `2024-06-30`)
+    before: Option<String>,
+}
+
+/// `POST /api/search/batch`: search for several queries in one round trip
+#[utoipa::path(
+    post,
+    path = "/api/search/batch",
+    request_body = BatchSearchReq,
+    responses(
+        (status = 200, description = "Search results for each query, in order", body = Vec<SearchRes>),
+        (status = 400, description = "Too many queries"),
+        (status = 429, description = "The client is searching too often"),
+    ),
+)]
+pub async fn search_batch(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    addr: Option<ConnectInfo<SocketAddr>>,
+    Json(req): Json<BatchSearchReq>,
+) -> Result<Response, AppError> {
+    if req.queries.len() > MAX_BATCH_QUERIES {
+        return Err(AppError::bad_request(format!(
+            "too many queries (at most {MAX_BATCH_QUERIES} per batch)"
+        )));
+    }
+    // Each query is a search, as far as rate limits go
+    let addr = addr.map(|ConnectInfo(addr)| addr);
+    if let Err(res) = ratelimit::take(&st, &headers, addr, req.queries.len().max(1)) {
+        return Ok(res);
+    }
+
+    let opts = SearchOptions {
+        explain: req.explain,
+        debug: req.debug,
+        lang: req
+            .lang
+            .unwrap_or_else(|| DEFAULT_LANG.to_string())
+            .to_ascii_lowercase(),
+        after: req.after,
+        before: req.before,
+    };
+
+    Ok(Json(search::search_batch(&st, req.queries, opts).await?).into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestParams {
+    /// What has been typed so far
+    #[serde(default)]
+    q: String,
+    /// Language to limit suggestions to (`all` for every language)
+    lang: Option<String>,
+}
+
+/// `GET /api/suggest`: suggest completions for a partially typed query
+///
+/// This follows the OpenSearch suggestions format (`[query, [suggestion, ...]]`), so browsers
+/// can show completions in the address bar.
+#[utoipa::path(
+    get,
+    path = "/api/suggest",
+    params(SuggestParams),
+    responses(
+        (status = 200, description = "The query and a list of suggested completions, as `[query, [suggestion, ...]]`"),
+    ),
+)]
+pub async fn suggest(
+    State(st): State<AppState>,
+    Query(params): Query<SuggestParams>,
+) -> Result<Json<(String, Vec<String>)>, AppError> {
+    let lang = params
+        .lang
+        .unwrap_or_else(|| DEFAULT_LANG.to_string())
+        .to_ascii_lowercase();
+
+    let suggestions = suggest::suggest(&st, &params.q, &lang)?;
+
+    Ok(Json((params.q, suggestions)))
+}
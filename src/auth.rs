@@ -0,0 +1,146 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{quota::Limits, AppState};
+
+/// API keys for protecting the admin and search APIs
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Keys allowed to use the admin API (which is disabled if there are none)
+    pub admin_keys: Vec<String>,
+    /// Keys allowed to use the search API, if it's protected
+    pub api_keys: Vec<String>,
+    /// Named keys allowed to use the search API, with their own quotas
+    #[serde(rename = "key")]
+    pub keys: Vec<ApiKey>,
+    /// Require an API key (or admin key) for `/api` routes
+    pub protect_api: bool,
+}
+
+/// A named API key, so its usage can be told apart from other keys'
+#[derive(Deserialize, Clone)]
+pub struct ApiKey {
+    /// Who the key belongs to, like a team or bot
+    pub name: String,
+    pub key: String,
+    /// Most requests the key can make in a minute
+    pub per_minute: Option<u64>,
+    /// Most requests the key can make in a day (UTC)
+    pub per_day: Option<u64>,
+}
+
+impl AuthConfig {
+    /// Get the name usage of `key` is counted under, or [None] if it's not a valid API key
+    ///
+    /// Unnamed keys are counted under where they are in `api_keys` (so no part of the key ends
+    /// up in the usage report or logs), and admin keys all together.
+    fn key_name(&self, key: &str) -> Option<String> {
+        if let Some(named) = self.keys.iter().find(|k| keys_match(&k.key, key)) {
+            Some(named.name.clone())
+        } else if let Some(i) = self.api_keys.iter().position(|k| keys_match(k, key)) {
+            Some(format!("key #{}", i + 1))
+        } else if is_valid_key(&self.admin_keys, key) {
+            Some("admin".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Get the quotas for the key named `name`
+    pub fn limits(&self, name: &str) -> Limits {
+        self.keys
+            .iter()
+            .find(|k| k.name == name)
+            .map_or_else(Limits::default, |k| Limits {
+                per_minute: k.per_minute,
+                per_day: k.per_day,
+            })
+    }
+}
+
+/// Get the key a request was made with
+///
+/// Keys can be given as `Authorization: Bearer <key>` or `X-Api-Key: <key>`.
+fn request_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|key| key.to_str().ok()))
+        .map(str::trim)
+}
+
+/// Check whether `key` is one of `keys`, without leaking how much of it matched
+fn is_valid_key(keys: &[String], key: &str) -> bool {
+    keys.iter().any(|k| keys_match(k, key))
+}
+
+/// Compare two keys in constant time
+fn keys_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "missing or invalid API key",
+    )
+        .into_response()
+}
+
+/// Middleware requiring an admin key
+pub async fn require_admin(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let config = st.config();
+    let auth = &config.auth;
+
+    let authorized =
+        request_key(req.headers()).is_some_and(|key| is_valid_key(&auth.admin_keys, key));
+    if authorized {
+        next.run(req).await
+    } else {
+        unauthorized()
+    }
+}
+
+/// Middleware requiring an API key, if the API is protected, and enforcing per-key quotas
+///
+/// Requests made with a valid key are counted against it even if the API isn't protected, so
+/// it's clear who's making them.
+pub async fn require_api_key(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let config = st.config();
+    let auth = &config.auth;
+
+    let Some(name) = request_key(req.headers()).and_then(|key| auth.key_name(key)) else {
+        return if auth.protect_api {
+            unauthorized()
+        } else {
+            next.run(req).await
+        };
+    };
+
+    match st.usage.check(&name, auth.limits(&name)) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => {
+            warn!(key = %name, "API key is over its quota");
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(
+                    header::RETRY_AFTER,
+                    retry_after.as_secs().max(1).to_string(),
+                )],
+                "this API key is over its quota",
+            )
+                .into_response()
+        }
+    }
+}
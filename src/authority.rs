@@ -0,0 +1,117 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use crate::{crawldb::CrawlDb, AppState};
+
+/// Chance of following a link instead of jumping to a random page
+const DAMPING: f64 = 0.85;
+/// Most PageRank iterations to run
+const MAX_ITERATIONS: usize = 50;
+/// Stop iterating once scores change by less than this in total
+const TOLERANCE: f64 = 1e-6;
+
+/// Ranking by authority, from how pages link to each other
+///
+/// Pages that many others link to (like a crate's index or a module overview) are usually
+/// better answers to broad queries than pages nothing links to.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AuthorityConfig {
+    pub enabled: bool,
+    /// How much authority boosts a result: its score is multiplied by
+    /// `1 + weight * ln(1 + authority)`, where the average page's authority is 1
+    pub weight: f32,
+}
+impl Default for AuthorityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            weight: 0.2,
+        }
+    }
+}
+
+/// Authority of each indexed page, computed with PageRank over the link graph
+///
+/// Scores are scaled so the average page's is 1. They're recomputed on startup and after
+/// every crawl, so pages indexed since then have the average score until the next one.
+#[derive(Default)]
+pub struct Authority {
+    scores: RwLock<HashMap<String, f32>>,
+}
+impl Authority {
+    /// Get a page's authority
+    pub fn score(&self, url: &str) -> f32 {
+        self.scores.read().unwrap().get(url).copied().unwrap_or(1.0)
+    }
+
+    /// Recompute every page's authority from the links recorded in `db`
+    pub fn refresh(&self, db: &CrawlDb) -> rusqlite::Result<()> {
+        let (urls, links) = db.link_graph()?;
+        let ranks = pagerank(urls.len(), &links);
+
+        let n = urls.len();
+        let scores = urls
+            .into_iter()
+            .zip(ranks)
+            .map(|(url, rank)| (url, (rank * n as f64) as f32))
+            .collect();
+        *self.scores.write().unwrap() = scores;
+
+        info!(pages = n, links = links.len(), "computed page authority");
+        Ok(())
+    }
+}
+
+/// Recompute every page's authority on a blocking thread, since PageRank over a big link graph
+/// can take a while
+///
+/// Replicas don't have the primary's link graph, so they skip this and don't boost by authority.
+pub async fn refresh(st: &AppState) {
+    if st.config().replica.is_some() {
+        return;
+    }
+
+    let (authority, db) = (st.authority.clone(), st.crawldb.clone());
+    match tokio::task::spawn_blocking(move || authority.refresh(&db)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => error!("couldn't compute page authority: {err}"),
+        Err(err) => error!("computing page authority panicked: {err}"),
+    }
+}
+
+/// Compute PageRank for `n` pages, given the links between them
+///
+/// Pages without any links spread their rank evenly over every page, so the ranks always add
+/// up to 1.
+fn pagerank(n: usize, links: &[(usize, usize)]) -> Vec<f64> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let mut out_degree = vec![0usize; n];
+    for &(from, _) in links {
+        out_degree[from] += 1;
+    }
+
+    let mut ranks = vec![1.0 / n as f64; n];
+    for _ in 0..MAX_ITERATIONS {
+        let dangling: f64 = (0..n)
+            .filter(|&page| out_degree[page] == 0)
+            .map(|page| ranks[page])
+            .sum();
+        let base = (1.0 - DAMPING + DAMPING * dangling) / n as f64;
+
+        let mut next = vec![base; n];
+        for &(from, to) in links {
+            next[to] += DAMPING * ranks[from] / out_degree[from] as f64;
+        }
+
+        let change: f64 = ranks.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+        ranks = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    ranks
+}
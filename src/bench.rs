@@ -0,0 +1,108 @@
+use std::{error::Error, fmt, time::Duration};
+
+use crate::{Engine, SearchOptions, Timings};
+
+/// The stages in a [BenchReport], in the order searches go through them
+const STAGES: [(&str, fn(&Timings) -> Duration); 7] = [
+    ("parse", |t| t.parse),
+    ("retrieve", |t| t.retrieve),
+    ("fetch", |t| t.fetch),
+    ("embed", |t| t.embed),
+    ("rerank", |t| t.rerank),
+    ("snippets", |t| t.snippets),
+    ("total", |t| t.total),
+];
+
+/// Latencies measured by [bench]
+pub struct BenchReport {
+    /// Number of searches run
+    pub searches: usize,
+    /// Latencies of each stage, sorted, in the order of `STAGES`
+    stages: Vec<Vec<Duration>>,
+}
+impl BenchReport {
+    /// Get the latency of `stage` at percentile `p` (from 0 to 100)
+    pub fn percentile(&self, stage: &str, p: f64) -> Option<Duration> {
+        let i = STAGES.iter().position(|(name, _)| *name == stage)?;
+        let times = &self.stages[i];
+        if times.is_empty() {
+            return None;
+        }
+
+        // Nearest rank
+        let rank = ((p / 100.0) * times.len() as f64).ceil() as usize;
+        Some(times[rank.clamp(1, times.len()) - 1])
+    }
+}
+
+/// Run each query `runs` times against the local index and measure how long each stage takes
+///
+/// The queries are run one at a time, so the latencies aren't skewed by searches competing with
+/// each other. Each query is run once before measuring, to warm up caches.
+pub async fn bench(
+    engine: &Engine,
+    queries: &[String],
+    runs: usize,
+    opts: SearchOptions,
+) -> Result<BenchReport, Box<dyn Error + Send + Sync>> {
+    let opts = SearchOptions {
+        debug: true,
+        ..opts
+    };
+
+    for q in queries {
+        engine.search(q, opts.clone()).await?;
+    }
+
+    let mut timings: Vec<Timings> = Vec::with_capacity(queries.len() * runs);
+    for _ in 0..runs {
+        for q in queries {
+            timings.push(
+                engine
+                    .search(q, opts.clone())
+                    .await?
+                    .timings
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    let stages = STAGES
+        .iter()
+        .map(|(_, time)| {
+            let mut times: Vec<_> = timings.iter().map(time).collect();
+            times.sort();
+            times
+        })
+        .collect();
+
+    Ok(BenchReport {
+        searches: timings.len(),
+        stages,
+    })
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |time: Option<Duration>| time.unwrap_or_default().as_secs_f64() * 1000.0;
+
+        writeln!(f, "{} searches", self.searches)?;
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>10} {:>10} {:>10}",
+            "stage", "p50 (ms)", "p90 (ms)", "p99 (ms)", "max (ms)"
+        )?;
+        for (stage, _) in STAGES {
+            writeln!(
+                f,
+                "{stage:<10} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+                ms(self.percentile(stage, 50.0)),
+                ms(self.percentile(stage, 90.0)),
+                ms(self.percentile(stage, 99.0)),
+                ms(self.percentile(stage, 100.0)),
+            )?;
+        }
+
+        Ok(())
+    }
+}
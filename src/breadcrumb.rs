@@ -0,0 +1,97 @@
+use spider::url::Url;
+
+use crate::extract::Extracted;
+
+/// Separator between the parts of a breadcrumb
+const SEPARATOR: &str = " › ";
+/// Path segments shown before the middle ones are collapsed
+const MAX_SEGMENTS: usize = 5;
+
+/// Build a breadcrumb for a page, like `docs.python.org › 3.13 › library › asyncio`
+///
+/// This comes from the URL's host and path, which for documentation sites usually shows the
+/// version and section a page is in. Pages at the root of their site (where the path says
+/// nothing) get their top heading instead.
+pub fn breadcrumb(url: &Url, page: &Extracted) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode(strip_extension(segment)))
+        .collect();
+    // `.../library/index.html` is the `library` page
+    if segments
+        .last()
+        .is_some_and(|last| matches!(last.as_str(), "index" | "default"))
+    {
+        segments.pop();
+    }
+
+    if segments.is_empty() {
+        if let Some(heading) = page
+            .sections
+            .first()
+            .filter(|heading| **heading != page.title)
+        {
+            segments.push(heading.clone());
+        }
+    }
+
+    if segments.len() > MAX_SEGMENTS {
+        let tail = segments.split_off(segments.len() - (MAX_SEGMENTS - 2));
+        segments.truncate(1);
+        segments.push("…".to_string());
+        segments.extend(tail);
+    }
+
+    std::iter::once(host.to_string())
+        .chain(segments)
+        .collect::<Vec<_>>()
+        .join(SEPARATOR)
+}
+
+/// Strip a file extension, like the `.html` in `asyncio.html`
+///
+/// Only short alphabetic extensions count, so version numbers like `3.13` are left alone.
+fn strip_extension(segment: &str) -> &str {
+    match segment.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && (1..=5).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphabetic()) =>
+        {
+            stem
+        }
+        _ => segment,
+    }
+}
+
+/// Decode percent escapes in a path segment, so `hello%20world` reads as `hello world`
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,33 @@
+use std::error::Error;
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use uuid::Uuid;
+
+/// Cookie the browser's ID is kept in
+const COOKIE: &str = "mini_search_id";
+/// How long the cookie lasts, in seconds
+const COOKIE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/// Get the browser's ID from its cookie, for keeping its history and saved pages
+///
+/// IDs are random UUIDs, which can't be guessed, so one browser can't see another's history or
+/// saved pages. The cookie is `SameSite=Lax`, so forms posted from other sites don't have it,
+/// and can't change anything.
+pub fn id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE)
+        .and_then(|(_, id)| Uuid::parse_str(id).ok())
+        .map(|id| id.to_string())
+}
+
+/// Make a cookie giving a browser a new ID
+pub fn new_cookie() -> Result<HeaderValue, Box<dyn Error + Send + Sync>> {
+    let id = Uuid::new_v4();
+    let cookie = format!("{COOKIE}={id}; Path=/; Max-Age={COOKIE_MAX_AGE}; HttpOnly; SameSite=Lax");
+    Ok(HeaderValue::from_str(&cookie)?)
+}
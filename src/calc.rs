@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use fend_core::Context as FendContext;
+
+/// Longest a calculation can run for, so something like `9^9^9^9` can't tie up a thread
+const TIME_LIMIT: Duration = Duration::from_millis(50);
+
+/// Stops `fend` once the time limit is up
+struct Deadline(Instant);
+impl fend_core::Interrupt for Deadline {
+    fn should_interrupt(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// Try to answer the query as a calculation or unit conversion
+///
+/// Returns [None] if `fend` can't make sense of the query, if the answer
+/// wouldn't tell the user anything they didn't already type, or if working it
+/// out takes too long. It's worked out on a blocking thread, since big numbers
+/// can take a while.
+pub async fn instant_answer(query: &str) -> Option<String> {
+    let query = query.trim();
+
+    // Plain words (`tokio spawn`, `e`, `pi`) are almost always searches,
+    // so only bother with queries that actually contain a number
+    if !query.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let query = query.to_string();
+    tokio::task::spawn_blocking(move || calculate(&query))
+        .await
+        .ok()
+        .flatten()
+}
+
+fn calculate(query: &str) -> Option<String> {
+    let mut ctx = FendContext::new();
+    let deadline = Deadline(Instant::now() + TIME_LIMIT);
+    let res = fend_core::evaluate_with_interrupt(query, &mut ctx, &deadline).ok()?;
+
+    // Unit types (e.g. `mm`) aren't answers
+    if res.is_unit_type() {
+        return None;
+    }
+
+    let answer = res.get_main_result().trim();
+    if answer.is_empty() || answer == query {
+        return None;
+    }
+
+    Some(answer.to_string())
+}
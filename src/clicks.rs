@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::HeaderMap,
+    response::Redirect,
+};
+use tantivy::{collector::Count, query::TermQuery, schema::IndexRecordOption, Term};
+
+use crate::{error::AppError, ratelimit, search::query_hash, store::JsonStore, AppState};
+
+/// How long a client's click on a result for a query only counts once
+const CLICK_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// Most recent clicks remembered for telling repeats apart, before they're cleaned up
+const MAX_RECENT_CLICKS: usize = 100_000;
+/// Most queries click counts are kept for
+const MAX_QUERIES: usize = 50_000;
+/// Most results click counts are kept for, for each query
+const MAX_RESULTS_PER_QUERY: usize = 50;
+
+/// Click tracking, for boosting the pages people pick for a query
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ClickConfig {
+    /// Record clicks on results (and use them for ranking)
+    pub enabled: bool,
+    /// How much clicks boost a result: its score is multiplied by `1 + weight * ln(1 + clicks)`
+    pub weight: f32,
+}
+impl Default for ClickConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            weight: 0.1,
+        }
+    }
+}
+
+/// How many times each result was clicked for each query
+///
+/// Queries are stored by their hash, like in the logs, so what people searched for isn't kept.
+/// Each client's clicks on a result for a query only count once a day, so one client can't push
+/// a page up by clicking it over and over.
+pub struct Clicks {
+    /// Clicks on each result, for each query (by hash)
+    counts: JsonStore<HashMap<String, HashMap<String, u32>>>,
+    /// When each client last clicked each result for each query (by hash)
+    recent: Mutex<HashMap<(IpAddr, String, String), Instant>>,
+}
+impl Clicks {
+    /// Load the saved click counts (or start from nothing, if there aren't any)
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            counts: JsonStore::load("clicks.json")?,
+            recent: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Save the click counts, if anything changed since they were last saved
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.counts.save()
+    }
+
+    /// Count a client's click on `url` for `q`, unless it already clicked it recently
+    fn record(&self, client: IpAddr, q: &str, url: &str) {
+        let hash = query_hash(q);
+        if !self.first_recent(client, &hash, url) {
+            return;
+        }
+
+        self.counts.change(|counts| {
+            if !counts.contains_key(&hash) && counts.len() >= MAX_QUERIES {
+                // Queries that were only clicked once say the least about what people want
+                counts.retain(|_, urls| urls.values().sum::<u32>() > 1);
+                if counts.len() >= MAX_QUERIES {
+                    return;
+                }
+            }
+            let urls = counts.entry(hash).or_default();
+            if !urls.contains_key(url) && urls.len() >= MAX_RESULTS_PER_QUERY {
+                return;
+            }
+            *urls.entry(url.to_string()).or_default() += 1;
+        });
+    }
+
+    /// Remember a client's click, and check whether it's the first in [CLICK_WINDOW]
+    fn first_recent(&self, client: IpAddr, hash: &str, url: &str) -> bool {
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= MAX_RECENT_CLICKS {
+            recent.retain(|_, clicked| now.duration_since(*clicked) < CLICK_WINDOW);
+            // If that many clicks are still recent, something is flooding clicks
+            if recent.len() >= MAX_RECENT_CLICKS {
+                return false;
+            }
+        }
+
+        let key = (client, hash.to_string(), url.to_string());
+        match recent.get(&key) {
+            Some(clicked) if now.duration_since(*clicked) < CLICK_WINDOW => false,
+            _ => {
+                recent.insert(key, now);
+                true
+            }
+        }
+    }
+
+    /// Get how many times `url` was clicked for `q`
+    pub fn count(&self, q: &str, url: &str) -> u32 {
+        self.counts.read(|counts| {
+            counts
+                .get(&query_hash(q))
+                .and_then(|urls| urls.get(url))
+                .copied()
+                .unwrap_or(0)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ClickParams {
+    /// URL of the result
+    u: String,
+    /// Query the result was clicked for
+    q: Option<String>,
+}
+
+/// `GET /click`: record a click on a result and redirect to it
+///
+/// Only indexed pages can be redirected to, so this can't be used as an open redirect.
+pub async fn click(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    addr: Option<ConnectInfo<SocketAddr>>,
+    Query(params): Query<ClickParams>,
+) -> Result<Redirect, AppError> {
+    let id = Term::from_field_text(st.schema.get_field("id")?, &params.u);
+    let indexed = st
+        .reader
+        .searcher()
+        .search(&TermQuery::new(id, IndexRecordOption::Basic), &Count)?;
+    if indexed == 0 {
+        return Err(AppError::bad_request("that page isn't in the index"));
+    }
+
+    if let Some(q) = params.q.filter(|q| !q.trim().is_empty()) {
+        let config = st.config();
+        if config.clicks.enabled {
+            let addr = addr.map(|ConnectInfo(addr)| addr);
+            let client = ratelimit::client_ip(&headers, addr, &config.rate_limit);
+            st.clicks.record(client, &q, &params.u);
+        }
+    }
+
+    Ok(Redirect::to(&params.u))
+}
@@ -0,0 +1,194 @@
+use std::{env, error::Error, fs, io::ErrorKind};
+
+use crate::{
+    auth::AuthConfig,
+    authority::AuthorityConfig,
+    clicks::ClickConfig,
+    crawler::{self, CrawlTarget, CrawlerConfig},
+    devdocs::DevDocsSource,
+    filter::UrlFilter,
+    fixtures::FixtureConfig,
+    history::HistoryConfig,
+    query::QueryConfig,
+    ratelimit::RateLimitConfig,
+    replica::ReplicaConfig,
+    server::TlsConfig,
+    title::{self, TitleRule},
+};
+
+/// Where the config file is read from, unless overridden by `MINI_SEARCH_CONFIG`
+const DEFAULT_PATH: &str = "mini-search.toml";
+
+/// Runtime configuration, loaded from `mini-search.toml`
+///
+/// Every field has a default, so the config file is optional.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Address to listen on (overridden by `MINI_SEARCH_LISTEN`)
+    ///
+    /// This can be a socket address (`127.0.0.1:8080`), a port (`8080`), or a Unix
+    /// socket (`unix:/run/mini-search.sock`).
+    pub listen: String,
+    /// URL the instance is reachable at (e.g. `https://search.example.com`)
+    ///
+    /// If unset, it's guessed from each request's `Host` header.
+    pub public_url: Option<String>,
+    /// Static ranking boosts for specific sources
+    #[serde(rename = "boost")]
+    pub boosts: Vec<Boost>,
+    /// Sites to crawl
+    #[serde(rename = "target")]
+    pub targets: Vec<CrawlTarget>,
+    /// DevDocs bundles to index along with the crawl targets
+    pub devdocs: Vec<DevDocsSource>,
+    /// How the crawler talks to sites
+    pub crawler: CrawlerConfig,
+    /// Pages that are never crawled or shown in results
+    pub filter: UrlFilter,
+    /// Crawl from saved pages instead of the network (or save crawled pages for later)
+    pub fixtures: Option<FixtureConfig>,
+    /// How queries are cleaned up before they're searched for
+    pub query: QueryConfig,
+    /// Replacements applied to page titles when they're indexed
+    #[serde(rename = "title_rule")]
+    pub title_rules: Vec<TitleRule>,
+    /// API keys for the admin and search APIs
+    pub auth: AuthConfig,
+    /// Per-client rate limits for searching
+    pub rate_limit: RateLimitConfig,
+    /// Click tracking, for ranking
+    pub clicks: ClickConfig,
+    /// Ranking by how pages link to each other
+    pub authority: AuthorityConfig,
+    /// Query history for each browser
+    pub history: HistoryConfig,
+    /// Serve HTTPS with this certificate and key, instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// Format to write logs in
+    pub log_format: LogFormat,
+    /// Serve a read-only copy of another instance's index, instead of crawling
+    ///
+    /// Switching into or out of replica mode needs a restart.
+    pub replica: Option<ReplicaConfig>,
+    /// How much memory indexing and the embedding model can use
+    pub memory: MemoryConfig,
+}
+
+/// Format to write logs in
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for shipping to a log aggregator
+    Json,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:8080".to_string(),
+            public_url: None,
+            boosts: Vec::new(),
+            targets: crawler::default_targets(),
+            devdocs: Vec::new(),
+            crawler: CrawlerConfig::default(),
+            filter: UrlFilter::default(),
+            fixtures: None,
+            query: QueryConfig::default(),
+            title_rules: title::default_rules(),
+            auth: AuthConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            clicks: ClickConfig::default(),
+            authority: AuthorityConfig::default(),
+            history: HistoryConfig::default(),
+            tls: None,
+            log_format: LogFormat::default(),
+            replica: None,
+            memory: MemoryConfig::default(),
+        }
+    }
+}
+
+/// A static ranking boost, multiplied into the fused score of matching results
+#[derive(Deserialize, Clone)]
+pub struct Boost {
+    /// URL prefix (without the scheme) this applies to, e.g. `docs.python.org/3.13/`
+    pub prefix: String,
+    /// Factor to multiply the score by (above 1 to prefer, below 1 to demote)
+    pub factor: f32,
+}
+
+/// How much memory indexing and the embedding model can use
+///
+/// The defaults suit a machine with a few GB of RAM. Lower them on a small VPS, or raise them to
+/// index and search faster on a big one. Changing the writer heap or concurrent inference needs
+/// a restart.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// Memory the index writer buffers documents in before flushing them to disk, in MB
+    ///
+    /// This is split between the writer's threads, which need at least 15 MB each, so a small
+    /// heap also means fewer indexing threads. Each thread can have at most 4 GB, so a heap over
+    /// 4 GB uses more threads, up to 8 (and 32 GB in all).
+    pub writer_heap_mb: usize,
+    /// Titles embedded at a time when indexing pushed documents or crawling as a worker
+    pub embedding_batch: usize,
+    /// Inferences the embedding model can run at once, across searches, crawls, and indexing
+    pub max_concurrent_inference: usize,
+}
+impl MemoryConfig {
+    pub fn writer_heap_bytes(&self) -> usize {
+        self.writer_heap_mb.saturating_mul(1_000_000)
+    }
+}
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            writer_heap_mb: 100,
+            embedding_batch: 32,
+            max_concurrent_inference: 1,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file (or the defaults, if there isn't one)
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let path = env::var("MINI_SEARCH_CONFIG").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+
+        let mut config: Self = match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                info!("no config found at {path}, using defaults");
+                Self::default()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Ok(listen) = env::var("MINI_SEARCH_LISTEN") {
+            config.listen = listen;
+        }
+
+        Ok(config)
+    }
+
+    /// Get the boost for a URL
+    ///
+    /// The longest matching prefix wins, so a general boost for a domain can be
+    /// overridden for specific paths under it.
+    pub fn boost_for(&self, url: &str) -> f32 {
+        let url = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .unwrap_or(url);
+
+        self.boosts
+            .iter()
+            .filter(|boost| url.starts_with(&boost.prefix))
+            .max_by_key(|boost| boost.prefix.len())
+            .map_or(1.0, |boost| boost.factor)
+    }
+}
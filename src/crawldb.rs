@@ -0,0 +1,300 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use crate::{
+    crawler::{self, ScrapedPage},
+    index::INDEX_DIR,
+};
+
+/// What's known about fetching a URL
+#[derive(Serialize, Clone, Debug)]
+pub struct PageMeta {
+    pub url: String,
+    pub source: String,
+    /// When the page was last fetched successfully, in seconds since the Unix epoch
+    pub fetched_at: Option<i64>,
+    /// `ETag` header from the last successful fetch
+    pub etag: Option<String>,
+    /// `Last-Modified` header from the last successful fetch
+    pub last_modified: Option<String>,
+    /// Hash of the page's HTML from the last successful fetch
+    pub content_hash: Option<String>,
+    /// Fetches that failed since the last successful one
+    pub failures: u32,
+    pub last_error: Option<String>,
+}
+
+/// A successful fetch, as recorded in the crawl database
+///
+/// Crawl workers send these to the primary along with the pages they crawled.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Fetch {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Hash of the page's HTML
+    pub content_hash: String,
+}
+impl Fetch {
+    pub fn new(page: &ScrapedPage) -> Self {
+        Self {
+            url: page.url.to_string(),
+            etag: page.etag.clone(),
+            last_modified: page.last_modified.clone(),
+            content_hash: stable_hash(&page.html),
+        }
+    }
+}
+
+/// A failed fetch, as recorded in the crawl database
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FailedFetch {
+    pub url: String,
+    pub error: String,
+}
+
+/// Crawl statistics for a source
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SourceMeta {
+    /// Pages that were ever fetched
+    pub pages: usize,
+    /// Pages whose last fetch failed
+    pub failing: usize,
+    /// When a page was last fetched successfully, in seconds since the Unix epoch
+    pub last_fetched: Option<i64>,
+}
+
+/// Metadata about every URL that was crawled, kept in `mini-search-index/crawl.db`
+///
+/// The index only has what's searched and shown, so this keeps track of everything else about
+/// fetching pages, like when they were fetched and whether they're failing.
+pub struct CrawlDb {
+    conn: Mutex<Connection>,
+}
+impl CrawlDb {
+    fn path() -> PathBuf {
+        Path::new(INDEX_DIR).join("crawl.db")
+    }
+
+    /// Open the database (or create it, if it doesn't exist)
+    pub fn open() -> rusqlite::Result<Self> {
+        Self::init(Connection::open(Self::path())?)
+    }
+
+    /// Open the database for reading only, so it can't change under a server using it
+    ///
+    /// If there's no database yet, this opens an empty one in memory.
+    pub fn read_only() -> rusqlite::Result<Self> {
+        if !Self::path().exists() {
+            return Self::init(Connection::open_in_memory()?);
+        }
+
+        let conn = Connection::open_with_flags(Self::path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Create the tables, if they don't exist
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pages (
+                url TEXT PRIMARY KEY,
+                source TEXT NOT NULL,
+                fetched_at INTEGER,
+                etag TEXT,
+                last_modified TEXT,
+                content_hash TEXT,
+                failures INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS pages_source ON pages (source);
+            CREATE TABLE IF NOT EXISTS links (
+                from_url TEXT NOT NULL,
+                to_url TEXT NOT NULL,
+                PRIMARY KEY (from_url, to_url)
+            );",
+        )?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Record a successful fetch, returning whether the page changed since the last one
+    pub fn fetched(&self, page: &ScrapedPage, source: &str) -> rusqlite::Result<bool> {
+        self.record_fetch(&Fetch::new(page), source)
+    }
+
+    /// Record a successful fetch (like one a crawl worker made), returning whether the page
+    /// changed since the last one
+    pub fn record_fetch(&self, fetch: &Fetch, source: &str) -> rusqlite::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+
+        let old: Option<Option<String>> = conn
+            .query_row(
+                "SELECT content_hash FROM pages WHERE url = ?1",
+                [fetch.url.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        conn.execute(
+            "INSERT INTO pages (url, source, fetched_at, etag, last_modified, content_hash)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (url) DO UPDATE SET
+                source = excluded.source,
+                fetched_at = excluded.fetched_at,
+                etag = excluded.etag,
+                last_modified = excluded.last_modified,
+                content_hash = excluded.content_hash,
+                failures = 0,
+                last_error = NULL",
+            params![
+                fetch.url,
+                source,
+                crawler::now().into_timestamp_secs(),
+                fetch.etag,
+                fetch.last_modified,
+                fetch.content_hash,
+            ],
+        )?;
+
+        Ok(old.flatten().as_deref() != Some(fetch.content_hash.as_str()))
+    }
+
+    /// Record a failed fetch
+    pub fn failed(&self, url: &str, source: &str, error: &str) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO pages (url, source, failures, last_error) VALUES (?1, ?2, 1, ?3)
+            ON CONFLICT (url) DO UPDATE SET
+                failures = failures + 1,
+                last_error = excluded.last_error",
+            params![url, source, error],
+        )?;
+
+        Ok(())
+    }
+
+    /// Get what's known about a URL
+    pub fn get(&self, url: &str) -> rusqlite::Result<Option<PageMeta>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT url, source, fetched_at, etag, last_modified, content_hash, failures,
+                    last_error
+                FROM pages WHERE url = ?1",
+                [url],
+                |row| {
+                    Ok(PageMeta {
+                        url: row.get(0)?,
+                        source: row.get(1)?,
+                        fetched_at: row.get(2)?,
+                        etag: row.get(3)?,
+                        last_modified: row.get(4)?,
+                        content_hash: row.get(5)?,
+                        failures: row.get(6)?,
+                        last_error: row.get(7)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    /// Forget a URL and the links on it, like when it's deleted from the index
+    pub fn remove(&self, url: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM pages WHERE url = ?1", [url])?;
+        conn.execute("DELETE FROM links WHERE from_url = ?1", [url])?;
+        Ok(())
+    }
+
+    /// Record the pages a page links to, replacing the ones recorded before
+    pub fn set_links(&self, url: &str, links: &[String]) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM links WHERE from_url = ?1", [url])?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT OR IGNORE INTO links (from_url, to_url) VALUES (?1, ?2)")?;
+            for link in links {
+                stmt.execute([url, link.as_str()])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Get the graph of links between indexed pages
+    ///
+    /// This returns every page that was fetched or had its links recorded, and the links
+    /// between them, as indexes into the list of pages. Links to anywhere else are left out.
+    pub fn link_graph(&self) -> rusqlite::Result<(Vec<String>, Vec<(usize, usize)>)> {
+        let conn = self.conn.lock().unwrap();
+        let urls: Vec<String> = conn
+            .prepare(
+                "SELECT url FROM pages WHERE fetched_at IS NOT NULL
+                UNION SELECT from_url FROM links",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        let edges = {
+            let ids: HashMap<&str, usize> = urls
+                .iter()
+                .enumerate()
+                .map(|(i, url)| (url.as_str(), i))
+                .collect();
+
+            let mut edges = Vec::new();
+            let mut stmt = conn.prepare("SELECT from_url, to_url FROM links")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let from: String = row.get(0)?;
+                let to: String = row.get(1)?;
+                if let (Some(&from), Some(&to)) = (ids.get(from.as_str()), ids.get(to.as_str())) {
+                    edges.push((from, to));
+                }
+            }
+            edges
+        };
+
+        Ok((urls, edges))
+    }
+
+    /// Get crawl statistics for each source
+    pub fn sources(&self) -> rusqlite::Result<BTreeMap<String, SourceMeta>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT source, COUNT(*), SUM(failures > 0), MAX(fetched_at)
+            FROM pages GROUP BY source",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                SourceMeta {
+                    pages: row.get(1)?,
+                    failing: row.get(2)?,
+                    last_fetched: row.get(3)?,
+                },
+            ))
+        })?;
+
+        rows.collect()
+    }
+}
+
+/// Hash some text, like a page's HTML to tell whether it changed
+///
+/// This is FNV-1a, which (unlike the standard library's hasher) is the same across Rust
+/// versions, so hashes in the database (and other saved state) stay comparable.
+pub fn stable_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
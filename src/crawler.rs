@@ -1,74 +1,558 @@
-use std::error::Error;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use spider::{
-    packages::scraper::{Html, Selector},
+    page::Page,
+    reqwest::header::{self, HeaderName},
     url::Url,
     website::Website,
 };
-use tantivy::TantivyDocument;
+use tantivy::{
+    schema::{Schema, Value},
+    DateTime, TantivyDocument,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    breadcrumb::breadcrumb,
+    crawldb::CrawlDb,
+    extract::{Extracted, Extractors},
+    fixtures::{self, FixtureMode},
+    index::{SearchIndex, INDEX_DIR},
+    sanitize, title,
+    transformers::Embedder,
+    Config,
+};
+
+/// A site to crawl
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrawlTarget {
+    /// Name of the source this belongs to (e.g. `docs.rs`), used for stats and filtering
+    pub source: String,
+    /// URL to start crawling from
+    pub url: String,
+    /// Only index pages whose path starts with one of these (any page, if empty)
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Don't index pages whose path ends with one of these
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+impl CrawlTarget {
+    fn new(source: &str, url: &str, include: &[&str], exclude: &[&str]) -> Self {
+        Self {
+            source: source.to_string(),
+            url: url.to_string(),
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Check whether this is from the source `filter`, or its start URL contains `filter`
+    pub fn matches(&self, filter: &str) -> bool {
+        self.source == filter || self.url.contains(filter)
+    }
+
+    /// Check whether a crawled page should be indexed
+    pub fn is_good_url(&self, url: &Url) -> bool {
+        let path = url.path();
+        (self.include.is_empty() || self.include.iter().any(|p| path.starts_with(p)))
+            && !self.exclude.iter().any(|s| path.ends_with(s))
+    }
+}
+
+/// How the crawler talks to sites
+///
+/// Each crawl only fetches pages from its start URL's host, so the connection limit is how hard
+/// a crawl can hit a site. Connections are kept alive and reused between requests.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CrawlerConfig {
+    /// Most requests in flight to a site at once
+    pub max_connections_per_host: usize,
+    /// Seconds to wait for a page before giving up on it
+    pub request_timeout_secs: u64,
+    /// Speak HTTP/2 from the start, without negotiating it first
+    ///
+    /// HTTP/2 is used anyway when a site offers it over HTTPS, so this only helps when every
+    /// target supports it. Sites that only speak HTTP/1.1 fail to crawl with this on.
+    pub http2_prior_knowledge: bool,
+}
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_host: 8,
+            request_timeout_secs: 30,
+            http2_prior_knowledge: false,
+        }
+    }
+}
+impl CrawlerConfig {
+    /// Apply these settings to a crawl
+    fn configure(&self, w: &mut Website) {
+        w.with_concurrency_limit(Some(self.max_connections_per_host.max(1)));
+        w.with_request_timeout(Some(Duration::from_secs(self.request_timeout_secs)));
+        w.with_http2_prior_knowledge(self.http2_prior_knowledge);
+    }
+}
+
+/// The sites crawled when the config doesn't list any
+pub fn default_targets() -> Vec<CrawlTarget> {
+    let mut targets = vec![
+        // Crawl only Python 3.13, 3.12, 3.8, and 2.7
+        CrawlTarget::new(
+            "docs.python.org",
+            "https://docs.python.org/3.13/",
+            &["/3.13", "/3.12", "/3.8", "/2.7"],
+            &[],
+        ),
+        CrawlTarget::new(
+            "docs.ruby-lang.org",
+            "https://docs.ruby-lang.org/",
+            &["/en/3.3", "/en/3.4", "/en/master"],
+            &["/index.html", "/"],
+        ),
+        CrawlTarget::new(
+            "doc.rust-lang.org",
+            "https://doc.rust-lang.org/stable/std/index.html",
+            &["/stable"],
+            &["/index.html", "/all.html"],
+        ),
+    ];
 
-use crate::{index::SearchIndex, transformers::SentEmbed};
+    for (name, version) in [
+        ("log", "0.4.22"),
+        ("tokio", "1.41.0"),
+        ("serde", "1.0.214"),
+        ("axum", "0.7.7"),
+        ("candle-core", "0.7.2"),
+        ("candle-nn", "0.7.2"),
+        ("candle-transformers", "0.7.2"),
+        ("spider", "2.11.20"),
+        ("tantivy", "0.22.0"),
+        ("tera", "1.20.0"),
+        ("tokenizers", "0.20.1"),
+        ("owo_colors", "4.1.0"),
+        ("fend_core", "1.5.3"),
+        ("pnet", "0.35.0"),
+    ] {
+        let base_path = format!("/{name}/{version}/{name}");
+        targets.push(CrawlTarget::new(
+            "docs.rs",
+            &format!("https://docs.rs{base_path}/index.html"),
+            &[&base_path],
+            &["/index.html", "/all.html"],
+        ));
+    }
+
+    targets
+}
+
+/// Number of pages indexed for each source by a crawl
+#[derive(Default)]
+pub struct CrawlStats {
+    pub sources: BTreeMap<String, usize>,
+}
+impl CrawlStats {
+    pub fn total(&self) -> usize {
+        self.sources.values().sum()
+    }
+}
+
+/// Progress saved when a crawl is interrupted, so the next one can pick up where it left off
+#[derive(Serialize, Deserialize, Default)]
+struct Checkpoint {
+    /// Start URLs of the targets that were completely crawled
+    done: Vec<String>,
+}
+impl Checkpoint {
+    fn path() -> PathBuf {
+        Path::new(INDEX_DIR).join("crawl-checkpoint.json")
+    }
+    fn load() -> Self {
+        fs::read(Self::path())
+            .ok()
+            .and_then(|buf| serde_json::from_slice(&buf).ok())
+            .unwrap_or_default()
+    }
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(Self::path(), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+    fn clear() {
+        let _ = fs::remove_file(Self::path());
+    }
+}
+
+/// Whether the last crawl was interrupted before it finished
+pub fn interrupted() -> bool {
+    Checkpoint::path().exists()
+}
+
+/// Crawl several targets one after another
+///
+/// If `cancel` is triggered, the crawl stops after the current page and a checkpoint is
+/// saved. The next crawl skips the targets that were finished before it was interrupted.
+pub async fn crawl_targets(
+    targets: &[CrawlTarget],
+    config: &Config,
+    extractors: &Extractors,
+    se: &Embedder,
+    index: &SearchIndex,
+    db: &CrawlDb,
+    cancel: &CancellationToken,
+) -> Result<CrawlStats, Box<dyn Error>> {
+    let mut stats = CrawlStats::default();
+    let mut checkpoint = Checkpoint::load();
+
+    for target in targets {
+        if checkpoint.done.contains(&target.url) {
+            info!(
+                source = %target.source,
+                url = %target.url,
+                "skipping target, it was crawled before the last interruption"
+            );
+            continue;
+        }
+
+        info!(source = %target.source, url = %target.url, "crawling target");
+        let ct = crawl(target, config, extractors, se, index, db, cancel).await?;
+        info!(source = %target.source, url = %target.url, pages = ct, "finished crawling target");
+        *stats.sources.entry(target.source.clone()).or_default() += ct;
+
+        if cancel.is_cancelled() {
+            info!("crawl interrupted, saving checkpoint");
+            checkpoint.save()?;
+            return Ok(stats);
+        }
+        checkpoint.done.push(target.url.clone());
+    }
+
+    Checkpoint::clear();
+
+    Ok(stats)
+}
 
 pub async fn crawl(
-    site: &str,
-    mut is_good_url: impl FnMut(Url) -> bool,
-    se: &mut SentEmbed,
+    target: &CrawlTarget,
+    config: &Config,
+    extractors: &Extractors,
+    se: &Embedder,
     index: &SearchIndex,
+    db: &CrawlDb,
+    cancel: &CancellationToken,
 ) -> Result<usize, Box<dyn Error>> {
-    let mut w = Website::new(site);
+    let mut total = 0usize;
+    // Committing makes a new segment and syncs it to disk, so pages are committed in batches
+    let mut batch = Vec::with_capacity(COMMIT_EVERY);
+
+    let pages = match &config.fixtures {
+        Some(fixture) if fixture.mode == FixtureMode::Replay => fixtures::replay(fixture, target)?,
+        _ => scrape(target, &config.crawler, cancel).await,
+    };
+    if let Some(fixture) = &config.fixtures {
+        if fixture.mode == FixtureMode::Record && !cancel.is_cancelled() {
+            fixtures::record(fixture, target, &pages)?;
+        }
+    }
+
+    for page in pages {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let url = &page.url;
+        if !config.filter.allows(url) {
+            debug!(source = %target.source, url = %url, "skipping blocked page");
+            continue;
+        }
+        if !page.ok() {
+            debug!(source = %target.source, url = %url, status = page.status, "fetch failed");
+            db.failed(url.as_str(), &target.source, &page.error())?;
+            continue;
+        }
+
+        let changed = db.fetched(&page, &target.source)?;
+        let (doc, _, links) = page_document(
+            url,
+            &page.html,
+            &target.source,
+            config,
+            extractors,
+            se,
+            index,
+        )
+        .await?;
+        db.set_links(url.as_str(), &links)?;
+        debug!(source = %target.source, url = %url, changed, "indexed page");
+        total += 1;
+
+        batch.push((url.to_string(), doc));
+        if batch.len() >= COMMIT_EVERY {
+            index.upsert_all(std::mem::take(&mut batch))?;
+        }
+    }
+    if !batch.is_empty() {
+        index.upsert_all(batch)?;
+    }
+
+    Ok(total)
+}
+
+/// Pages indexed between commits while crawling
+pub const COMMIT_EVERY: usize = 500;
+
+/// Most pages crawled for a single target
+pub const MAX_PAGES: usize = 10_000;
+
+/// A page fetched while crawling
+pub struct ScrapedPage {
+    pub url: Url,
+    pub html: String,
+    /// HTTP status code
+    pub status: u16,
+    /// `ETag` header, for telling whether the page changed
+    pub etag: Option<String>,
+    /// `Last-Modified` header, for telling whether the page changed
+    pub last_modified: Option<String>,
+}
+impl ScrapedPage {
+    fn new(page: &Page, url: Url) -> Self {
+        let get_header = |name: HeaderName| {
+            page.headers
+                .as_ref()?
+                .get(name)?
+                .to_str()
+                .ok()
+                .map(str::to_string)
+        };
+
+        Self {
+            html: page.get_html(),
+            status: page.status_code.as_u16(),
+            etag: get_header(header::ETAG),
+            last_modified: get_header(header::LAST_MODIFIED),
+            url,
+        }
+    }
+
+    /// Whether the page was fetched successfully
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.status) && !self.html.is_empty()
+    }
+
+    /// Describe why the page couldn't be fetched
+    pub fn error(&self) -> String {
+        if self.status == 0 {
+            "couldn't connect".to_string()
+        } else if self.html.is_empty() && (200..300).contains(&self.status) {
+            "empty response".to_string()
+        } else {
+            format!("HTTP {}", self.status)
+        }
+    }
+}
+
+/// Crawl a target's site, returning each page that should be indexed
+///
+/// Pages that couldn't be fetched are included too, so failures can be recorded. This returns
+/// nothing if `cancel` is triggered while crawling.
+pub async fn scrape(
+    target: &CrawlTarget,
+    http: &CrawlerConfig,
+    cancel: &CancellationToken,
+) -> Vec<ScrapedPage> {
+    let mut w = Website::new(&target.url);
+    http.configure(&mut w);
     w.with_respect_robots_txt(true);
     w.with_block_assets(true);
-    w.with_limit(10_000);
+    w.with_limit(MAX_PAGES as u32);
     //w.with_limit(40);
 
+    tokio::select! {
+        _ = w.scrape() => {}
+        _ = cancel.cancelled() => return Vec::new(),
+    }
+
+    let Some(pages) = w.get_pages() else {
+        return Vec::new();
+    };
+    pages
+        .iter()
+        .filter_map(|page| {
+            let url = page.get_url_parsed().as_ref()?;
+            target
+                .is_good_url(url)
+                .then(|| ScrapedPage::new(page, url.clone()))
+        })
+        .take(MAX_PAGES)
+        .collect()
+}
+
+/// Fetch a single page, without following any links
+///
+/// This returns [None] if robots.txt doesn't allow fetching it.
+pub async fn fetch(url: &Url, http: &CrawlerConfig) -> Option<ScrapedPage> {
+    let mut w = Website::new(url.as_str());
+    http.configure(&mut w);
+    w.with_respect_robots_txt(true);
+    w.with_limit(1);
+
     w.scrape().await;
 
-    let mut writer = index.writer()?;
+    let page = w.get_pages()?.first()?;
+    Some(ScrapedPage::new(page, url.clone()))
+}
 
-    let mut total = 0usize;
+/// Name of the source a page belongs to
+///
+/// This is the source of the first target the page would've been crawled for, or else the
+/// page's host.
+pub fn source_for(targets: &[CrawlTarget], url: &Url) -> String {
+    targets
+        .iter()
+        .find(|t| {
+            Url::parse(&t.url).is_ok_and(|start| start.host_str() == url.host_str())
+                && t.is_good_url(url)
+        })
+        .map(|t| t.source.clone())
+        .unwrap_or_else(|| url.host_str().unwrap_or_default().to_string())
+}
 
-    'index: for page in w.get_pages().unwrap().iter() {
-        if total == 10_000 {
-            break 'index;
-        }
-        if let Some(url) = page.get_url_parsed() {
-            if is_good_url(url.clone()) {
-                let html = Html::parse_document(&page.get_html());
-
-                let body = html
-                    .select(&Selector::parse("p, h1, h2, h3, h4").unwrap())
-                    .map(|elem| elem.text().collect::<Vec<_>>().join(" "))
-                    .collect::<Vec<_>>()
-                    .join(" ");
-
-                let title = html
-                    .select(&Selector::parse("title").unwrap())
-                    .next()
-                    .map(|x| x.inner_html())
-                    .unwrap_or(url.to_string());
-
-                let embedding = se.generate_embedding(title.clone())?;
-                let embedding: Vec<u8> = unsafe {
-                    core::slice::from_raw_parts(
-                        embedding.as_ptr() as *const u8,
-                        embedding.len() * 4,
-                    )
-                    .to_vec()
-                };
-
-                let schema = index.schema();
-                let mut doc = TantivyDocument::new();
-                doc.add_text(schema.get_field("url")?, url);
-                doc.add_text(schema.get_field("title")?, title);
-                doc.add_text(schema.get_field("body")?, body);
-                doc.add_bytes(schema.get_field("embedding")?, embedding);
-
-                writer.add_document(doc)?;
-                writer.commit()?;
-                total += 1;
-            }
-        }
+/// Extract, embed, and index a page, replacing any existing copy of it, and commit it
+///
+/// This returns the page's title and the pages it links to.
+pub async fn index_page(
+    url: &Url,
+    html: &str,
+    source: &str,
+    config: &Config,
+    extractors: &Extractors,
+    se: &Embedder,
+    index: &SearchIndex,
+) -> Result<(String, Vec<String>), Box<dyn Error>> {
+    let (doc, title, links) =
+        page_document(url, html, source, config, extractors, se, index).await?;
+    index.upsert(url.as_str(), doc)?;
+
+    Ok((title, links))
+}
+
+/// Extract and embed a page, and build its document
+///
+/// Embedding is the slow part, so if the page is already indexed with the same title, its
+/// embedding is reused. Everything else is extracted again, so changes to the title rules or
+/// extractors apply on the next crawl, and `indexed_at` is always when the page was crawled.
+///
+/// This returns the document, along with the page's title and the pages it links to.
+async fn page_document(
+    url: &Url,
+    html: &str,
+    source: &str,
+    config: &Config,
+    extractors: &Extractors,
+    se: &Embedder,
+    index: &SearchIndex,
+) -> Result<(TantivyDocument, String, Vec<String>), Box<dyn Error>> {
+    // The parsed HTML can't be held across an await, so extract everything up front
+    let mut page = extractors.extract(url, html);
+    page.title = title::clean(&config.title_rules, source, &page.title);
+    let title = page.title.clone();
+    let links = page.links.clone();
+
+    let schema = index.schema();
+    // The model could have changed since, if the embedding isn't the size it makes
+    let indexed = indexed_embedding(index, &schema, url, &title)?.filter(|e| e.len() == se.dim());
+    let embedding = match indexed {
+        Some(embedding) => embedding,
+        None => se.acquire().await.generate_embedding(title.clone())?,
+    };
+    let doc = document(&schema, url, page, source, &embedding)?;
+
+    Ok((doc, title, links))
+}
+
+/// Get the embedding of an indexed page's title, if it's indexed with `title`
+fn indexed_embedding(
+    index: &SearchIndex,
+    schema: &Schema,
+    url: &Url,
+    title: &str,
+) -> Result<Option<Vec<f32>>, Box<dyn Error>> {
+    let Some(doc) = index.get(url.as_str())? else {
+        return Ok(None);
+    };
+    let indexed_title = doc
+        .get_first(schema.get_field("title")?)
+        .and_then(|title| title.as_str());
+    // Titles are cleaned up when they're indexed
+    if indexed_title != Some(sanitize::text(title).as_str()) {
+        return Ok(None);
     }
 
-    Ok(total)
+    Ok(doc
+        .get_first(schema.get_field("embedding")?)
+        .and_then(|embedding| embedding.as_bytes())
+        .map(|embedding| {
+            embedding
+                .chunks_exact(4)
+                .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        }))
+}
+
+/// Build the document for a page, given the embedding of its title
+pub fn document(
+    schema: &Schema,
+    url: &Url,
+    page: Extracted,
+    source: &str,
+    embedding: &[f32],
+) -> Result<TantivyDocument, Box<dyn Error>> {
+    let breadcrumb = breadcrumb(url, &page);
+    // Everything shown on the results page is cleaned up, in case a page has junk in it
+    let description = page.description().map(sanitize::text);
+    let page_type = page.page_type().map(sanitize::text);
+    let image = page.image().and_then(sanitize::web_url);
+    let title = sanitize::text(&page.title);
+    let Extracted { body, lang, .. } = page;
+
+    let embedding: Vec<u8> = unsafe {
+        core::slice::from_raw_parts(embedding.as_ptr() as *const u8, embedding.len() * 4).to_vec()
+    };
+
+    let mut doc = TantivyDocument::new();
+    doc.add_text(schema.get_field("url")?, url.as_str());
+    doc.add_text(schema.get_field("title")?, title);
+    doc.add_text(schema.get_field("body")?, body);
+    doc.add_text(schema.get_field("breadcrumb")?, breadcrumb);
+    if let Some(description) = description {
+        doc.add_text(schema.get_field("description")?, description);
+    }
+    if let Some(page_type) = page_type {
+        doc.add_text(schema.get_field("page_type")?, page_type);
+    }
+    if let Some(image) = image {
+        doc.add_text(schema.get_field("image")?, image);
+    }
+    doc.add_bytes(schema.get_field("embedding")?, embedding);
+    doc.add_text(schema.get_field("lang")?, lang);
+    doc.add_text(schema.get_field("source")?, source);
+    doc.add_date(schema.get_field("indexed_at")?, now());
+
+    Ok(doc)
+}
+
+/// Get the current time, for recording when pages were indexed
+pub fn now() -> DateTime {
+    DateTime::from_timestamp_secs(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64,
+    )
 }
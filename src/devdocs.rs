@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf, time::Duration};
+
+use spider::{
+    packages::scraper::{ElementRef, Html, Selector},
+    reqwest::Client,
+    url::Url,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    crawldb::CrawlDb,
+    crawler::{self, ScrapedPage},
+    extract::{Extracted, Extractors},
+    index::SearchIndex,
+    title,
+    transformers::Embedder,
+    Config,
+};
+
+/// Where bundles' entry indexes are downloaded from, by slug
+const INDEX_URL: &str = "https://devdocs.io/docs";
+/// Where bundles' pages are downloaded from, by slug
+const DB_URL: &str = "https://documents.devdocs.io";
+/// Pages parsed at a time, before their documents are embedded and indexed
+const PAGES_PER_BATCH: usize = 64;
+
+/// A DevDocs documentation bundle to index, like `javascript` or `css`
+///
+/// DevDocs (<https://devdocs.io>) packages documentation from many projects, including MDN's
+/// web platform docs, as a bundle of pages and an index of every entry in them. Each entry
+/// (like `Array.prototype.map()`) is indexed with its own title, and entries that point at a
+/// section of a page are indexed as that section, linked with its anchor.
+#[derive(Deserialize, Clone)]
+pub struct DevDocsSource {
+    /// DevDocs' name for the bundle, like `javascript`, `css`, or `dom`
+    pub slug: String,
+    /// Name of the source the entries are filed under (`devdocs.io` by default)
+    pub source: Option<String>,
+    /// Directory with the bundle's `index.json` and `db.json`, instead of downloading them
+    pub dir: Option<PathBuf>,
+    /// URL entries link to, which their paths are relative to (`https://devdocs.io/<slug>/`
+    /// by default)
+    pub base_url: Option<String>,
+}
+impl DevDocsSource {
+    pub fn source(&self) -> String {
+        self.source
+            .clone()
+            .unwrap_or_else(|| "devdocs.io".to_string())
+    }
+
+    /// Check whether this is from the source `filter`, or its slug is `filter`
+    pub fn matches(&self, filter: &str) -> bool {
+        self.source() == filter || self.slug == filter
+    }
+
+    fn base_url(&self) -> Result<Url, Box<dyn Error>> {
+        let base = match &self.base_url {
+            Some(base) => base.clone(),
+            None => format!("https://devdocs.io/{}/", self.slug),
+        };
+        // Paths are joined onto the base, so it has to end with a slash
+        let base = if base.ends_with('/') {
+            base
+        } else {
+            format!("{base}/")
+        };
+        Ok(Url::parse(&base)?)
+    }
+
+    /// Read the bundle from its directory, or download it
+    async fn load(&self) -> Result<(EntryIndex, BTreeMap<String, String>), Box<dyn Error>> {
+        let (index, db) = match &self.dir {
+            Some(dir) => (
+                fs::read(dir.join("index.json"))?,
+                fs::read(dir.join("db.json"))?,
+            ),
+            None => {
+                let client = Client::builder()
+                    .timeout(Duration::from_secs(5 * 60))
+                    .build()?;
+                let get = |url: String| {
+                    let client = &client;
+                    async move {
+                        let res = client.get(url).send().await?.error_for_status()?;
+                        res.bytes().await
+                    }
+                };
+                (
+                    get(format!("{INDEX_URL}/{}/index.json", self.slug))
+                        .await?
+                        .to_vec(),
+                    get(format!("{DB_URL}/{}/db.json", self.slug))
+                        .await?
+                        .to_vec(),
+                )
+            }
+        };
+
+        Ok((
+            serde_json::from_slice(&index)?,
+            serde_json::from_slice(&db)?,
+        ))
+    }
+}
+
+/// A bundle's `index.json`, listing its entries
+#[derive(Deserialize)]
+struct EntryIndex {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    name: String,
+    /// Path of the entry's page, with the anchor of its section if it's part of one
+    path: String,
+    /// Group the entry is listed under, like `Global Objects`
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Index a DevDocs bundle, replacing any existing copies of its entries
+///
+/// Returns the number of documents indexed.
+pub async fn import(
+    bundle: &DevDocsSource,
+    config: &Config,
+    extractors: &Extractors,
+    se: &Embedder,
+    index: &SearchIndex,
+    db: &CrawlDb,
+    cancel: &CancellationToken,
+) -> Result<usize, Box<dyn Error>> {
+    let base = bundle.base_url()?;
+    let source = bundle.source();
+    let (entries, pages) = bundle.load().await?;
+
+    // Group the entries by the page they're on
+    let mut by_page: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+    for entry in &entries.entries {
+        let page = entry.path.split('#').next().unwrap_or_default();
+        by_page.entry(page).or_default().push(entry);
+    }
+    let by_page: Vec<_> = by_page.into_iter().collect();
+
+    let schema = index.schema();
+    let batch_size = config.memory.embedding_batch.max(1);
+    let mut total = 0;
+    for chunk in by_page.chunks(PAGES_PER_BATCH) {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        // The parsed HTML can't be held across an await, so extract everything up front
+        let mut docs = Vec::new();
+        for (path, page_entries) in chunk {
+            let Some(html) = pages.get(*path) else {
+                debug!(slug = %bundle.slug, path = %path, "entry's page isn't in the bundle");
+                continue;
+            };
+            let url = base.join(path)?;
+            if !config.filter.allows(&url) {
+                continue;
+            }
+
+            let scraped = ScrapedPage {
+                url: url.clone(),
+                html: html.clone(),
+                status: 200,
+                etag: None,
+                last_modified: None,
+            };
+            db.fetched(&scraped, &source)?;
+
+            for (url, mut page) in page_docs(&url, html, page_entries, extractors) {
+                page.title = title::clean(&config.title_rules, &source, &page.title);
+                db.set_links(url.as_str(), &page.links)?;
+                docs.push((url, page));
+            }
+        }
+
+        for batch in docs.chunks(batch_size) {
+            let titles = batch.iter().map(|(_, page)| page.title.clone()).collect();
+            let embeddings = se.acquire().await.generate_embeddings(titles)?;
+
+            let mut indexed = Vec::with_capacity(batch.len());
+            for ((url, page), embedding) in batch.iter().zip(embeddings) {
+                let doc = crawler::document(&schema, url, page.clone(), &source, &embedding)?;
+                indexed.push((url.to_string(), doc));
+            }
+            total += indexed.len();
+            index.upsert_all(indexed)?;
+        }
+    }
+
+    Ok(total)
+}
+
+/// Build the documents for a page: one for the page itself, and one for each section that has
+/// its own entry
+fn page_docs(
+    url: &Url,
+    html: &str,
+    entries: &[&Entry],
+    extractors: &Extractors,
+) -> Vec<(Url, Extracted)> {
+    let mut page = extractors.extract(url, html);
+    // Entry types (like `Global Objects`) are shown as the page's type
+    if let Some(entry) = entries.first() {
+        page.metadata
+            .insert("og:type".to_string(), entry.kind.clone());
+    }
+
+    // Pages have no `<title>`, so they're named after their entry
+    let page_entry = entries.iter().find(|entry| !entry.path.contains('#'));
+    page.title = match page_entry {
+        Some(entry) => entry.name.clone(),
+        None => page
+            .sections
+            .first()
+            .cloned()
+            .unwrap_or_else(|| entries[0].name.clone()),
+    };
+
+    let sections: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| Some((entry, entry.path.split_once('#')?.1)))
+        .collect();
+    let mut docs = Vec::with_capacity(1 + sections.len());
+    if !sections.is_empty() {
+        let html = Html::parse_fragment(html);
+        for (entry, anchor) in sections {
+            let Some(body) = section_text(&html, anchor) else {
+                continue;
+            };
+
+            let mut section_url = url.clone();
+            section_url.set_fragment(Some(anchor));
+            docs.push((
+                section_url,
+                Extracted {
+                    title: entry.name.clone(),
+                    body,
+                    lang: page.lang.clone(),
+                    metadata: [("og:type".to_string(), entry.kind.clone())].into(),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+    docs.insert(0, (url.clone(), page));
+
+    docs
+}
+
+/// Get the text of the section of a page with the anchor `id`
+///
+/// If the anchor is on a heading, the section runs until the next heading at the same level or
+/// above. Otherwise, it's just the element with the anchor.
+fn section_text(html: &Html, id: &str) -> Option<String> {
+    let with_id = Selector::parse("[id]").unwrap();
+    let elem = html
+        .select(&with_id)
+        .find(|elem| elem.value().id() == Some(id))?;
+    let text = |elem: ElementRef| elem.text().collect::<Vec<_>>().join(" ");
+
+    let Some(level) = heading_level(elem.value().name()) else {
+        return Some(text(elem));
+    };
+    let mut body = vec![text(elem)];
+    for sibling in elem.next_siblings().filter_map(ElementRef::wrap) {
+        if heading_level(sibling.value().name()).is_some_and(|l| l <= level) {
+            break;
+        }
+        body.push(text(sibling));
+    }
+
+    Some(body.join(" "))
+}
+
+/// Level of a heading element (like 2 for `h2`), or [None] if it isn't one
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
@@ -0,0 +1,173 @@
+use std::{error::Error, fs, path::Path};
+
+use rusqlite::Connection;
+use tantivy::{
+    schema::{Field, Value},
+    TantivyDocument,
+};
+use tera::escape_html;
+
+use crate::SearchIndex;
+
+/// Export the index as a Dash docset (which Zeal can open too), returning how many pages it has
+///
+/// Only the text of each page is indexed, so the docset has a plain page for each one, with its
+/// title, body text, and a link to the original. Entry types come from the pages' OpenGraph or
+/// schema.org types, or are guessed from rustdoc URLs (like `struct.HashMap.html`), and
+/// everything else is a guide.
+///
+/// `dir` is the docset to create, like `mini-search.docset`, and must not already exist.
+pub fn export_docset(index: &SearchIndex, dir: &Path, name: &str) -> Result<usize, Box<dyn Error>> {
+    if dir.exists() {
+        return Err(format!("{} already exists", dir.display()).into());
+    }
+
+    let resources = dir.join("Contents/Resources");
+    fs::create_dir_all(resources.join("Documents/pages"))?;
+
+    fs::write(dir.join("Contents/Info.plist"), info_plist(name))?;
+
+    let mut db = Connection::open(resources.join("docSet.dsidx"))?;
+    db.execute_batch(
+        "CREATE TABLE searchIndex(id INTEGER PRIMARY KEY, name TEXT, type TEXT, path TEXT);
+         CREATE UNIQUE INDEX anchor ON searchIndex (name, type, path);",
+    )?;
+
+    let schema = index.schema();
+    let url_field = schema.get_field("url")?;
+    let title_field = schema.get_field("title")?;
+    let body_field = schema.get_field("body")?;
+    let page_type_field = schema.get_field("page_type")?;
+    let text = |doc: &TantivyDocument, field: Field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let tx = db.transaction()?;
+    let mut count = 0;
+    {
+        let mut insert =
+            tx.prepare("INSERT OR IGNORE INTO searchIndex(name, type, path) VALUES (?1, ?2, ?3)")?;
+
+        let searcher = index.reader().searcher();
+        for segment in searcher.segment_readers() {
+            let store = segment.get_store_reader(1)?;
+            for doc in store.iter::<TantivyDocument>(segment.alive_bitset()) {
+                let doc = doc?;
+                let url = text(&doc, url_field);
+                let title = text(&doc, title_field);
+                let body = text(&doc, body_field);
+
+                let path = format!("pages/{count}.html");
+                fs::write(
+                    resources.join("Documents").join(&path),
+                    page(&url, &title, &body),
+                )?;
+                let page_type = doc
+                    .get_first(page_type_field)
+                    .and_then(|value| value.as_str());
+                insert.execute((&title, entry_type(&url, page_type), &path))?;
+                count += 1;
+            }
+        }
+    }
+    tx.commit()?;
+
+    fs::write(
+        resources.join("Documents/index.html"),
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{name}</title></head>\
+             <body><h1>{name}</h1><p>{count} pages from Mini Search.</p></body></html>\n",
+            name = escape_html(name),
+        ),
+    )?;
+
+    Ok(count)
+}
+
+/// Get a page's Dash entry type from its stored type, or else guess it from its URL
+fn entry_type(url: &str, page_type: Option<&str>) -> &'static str {
+    if let Some(kind) = page_type.and_then(dash_type) {
+        return kind;
+    }
+
+    let file = url.rsplit('/').next().unwrap_or_default();
+    let kind = file.split_once('.').map_or("", |(kind, _)| kind);
+
+    match kind {
+        "struct" => "Struct",
+        "enum" => "Enum",
+        "trait" => "Trait",
+        "fn" => "Function",
+        "macro" | "derive" => "Macro",
+        "attr" => "Attribute",
+        "constant" => "Constant",
+        "static" => "Global",
+        "type" => "Type",
+        "union" => "Union",
+        "primitive" => "Builtin",
+        "keyword" => "Keyword",
+        _ => "Guide",
+    }
+}
+
+/// Get the Dash entry type for a page's OpenGraph or schema.org type (like `article` or
+/// `TechArticle`), or a DevDocs entry type (like `Methods`), if there's one that fits
+fn dash_type(page_type: &str) -> Option<&'static str> {
+    // DevDocs groups entries under plurals, like `Functions` or `Classes`
+    Some(match page_type.trim().to_lowercase().as_str() {
+        "class" | "classes" => "Class",
+        "struct" | "structs" => "Struct",
+        "enum" | "enums" => "Enum",
+        "trait" | "traits" => "Trait",
+        "interface" | "interfaces" => "Interface",
+        "function" | "functions" => "Function",
+        "method" | "methods" => "Method",
+        "property" | "properties" => "Property",
+        "module" | "modules" => "Module",
+        "constant" | "constants" => "Constant",
+        "type" | "types" => "Type",
+        "macro" | "macros" => "Macro",
+        "keyword" | "keywords" => "Keyword",
+        "event" | "events" => "Event",
+        "element" | "elements" => "Element",
+        "attribute" | "attributes" => "Attribute",
+        "article" | "techarticle" | "howto" | "guide" | "guides" | "tutorial" => "Guide",
+        _ => return None,
+    })
+}
+
+/// The page for one document
+fn page(url: &str, title: &str, body: &str) -> String {
+    let (url, title, body) = (escape_html(url), escape_html(title), escape_html(body));
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\
+         <body><h1>{title}</h1><p><a href=\"{url}\">{url}</a></p><p>{body}</p></body></html>\n"
+    )
+}
+
+fn info_plist(name: &str) -> String {
+    let name = escape_html(name);
+    let id = name.to_ascii_lowercase().replace(' ', "-");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>CFBundleIdentifier</key>
+	<string>{id}</string>
+	<key>CFBundleName</key>
+	<string>{name}</string>
+	<key>DocSetPlatformFamily</key>
+	<string>{id}</string>
+	<key>dashIndexFilePath</key>
+	<string>index.html</string>
+	<key>isDashDocset</key>
+	<true/>
+</dict>
+</plist>
+"#
+    )
+}
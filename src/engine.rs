@@ -0,0 +1,270 @@
+use std::{
+    error::Error,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::Router;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    admin::CrawlJobs,
+    authority::{self, Authority},
+    clicks::Clicks,
+    crawldb::CrawlDb,
+    crawler, history, i18n,
+    queue::WorkQueue,
+    quota::Usage,
+    ratelimit::RateLimiter,
+    replica, saved, search, server, systemd, templates, AppState, Config, Embedder, Extractor,
+    Extractors, SearchIndex, SearchOptions, SearchRes, SentEmbed,
+};
+
+/// How often click counts and history are saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Mini Search, with its index and embedding model loaded
+///
+/// This is cheap to clone, and clones share everything.
+#[derive(Clone)]
+pub struct Engine {
+    st: AppState,
+    /// Whether the index was opened read-only, so it can't be crawled
+    read_only: bool,
+}
+
+/// Builder for an [Engine]
+#[derive(Default)]
+pub struct EngineBuilder {
+    config: Option<Config>,
+    shutdown: Option<CancellationToken>,
+    extractors: Extractors,
+    read_only: bool,
+}
+impl EngineBuilder {
+    /// Use this config, instead of loading the config file
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Stop crawling and serving when this is cancelled
+    pub fn shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Open the index and crawl database read-only, and don't save anything in the background
+    ///
+    /// This works while a server has the index open, for searching it from another process
+    /// (like the `bench` command does). The engine can't crawl, and shouldn't be served.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Extract pages on `domain` (and its subdomains) with `extractor`, instead of the default
+    /// [SelectorExtractor](crate::SelectorExtractor)
+    pub fn extractor(mut self, domain: &str, extractor: impl Extractor + 'static) -> Self {
+        self.extractors.register(domain, extractor);
+        self
+    }
+
+    /// Load the templates, the embedding model, and the search index
+    ///
+    /// The model is loaded from the working directory, and the index is opened (or created) in
+    /// `mini-search-index` there.
+    pub async fn build(self) -> Result<Engine, Box<dyn Error>> {
+        let config = match self.config {
+            Some(config) => config,
+            None => Config::load()?,
+        };
+
+        let templates = templates::load()?;
+        let locales = Arc::new(i18n::load()?);
+        let se = Arc::new(Embedder::new(
+            SentEmbed::new()?,
+            config.memory.max_concurrent_inference,
+        ));
+        tokio::task::spawn_blocking({
+            let se = se.clone();
+            move || se.warm_up()
+        })
+        .await?;
+        let (index, crawldb) = if self.read_only {
+            (SearchIndex::read_only().await?, CrawlDb::read_only()?)
+        } else if config.replica.is_some() {
+            (SearchIndex::replica().await?, CrawlDb::open()?)
+        } else {
+            (
+                SearchIndex::new(config.memory.writer_heap_bytes()).await?,
+                CrawlDb::open()?,
+            )
+        };
+        let index = Arc::new(index);
+
+        let engine = Engine {
+            st: AppState {
+                reader: index.reader(),
+                parser: index.query_parser(),
+                schema: index.schema(),
+                index,
+                crawldb: Arc::new(crawldb),
+                se,
+                extractors: Arc::new(self.extractors),
+                templates,
+                locales,
+                config: Arc::new(RwLock::new(Arc::new(config))),
+                jobs: CrawlJobs::default(),
+                clicks: Arc::new(Clicks::load()?),
+                authority: Arc::new(Authority::default()),
+                saved: Arc::new(saved::load()?),
+                history: Arc::new(history::load()?),
+                queue: WorkQueue::default(),
+                limiter: Arc::new(RateLimiter::default()),
+                usage: Arc::new(Usage::default()),
+                shutdown: self.shutdown.unwrap_or_default(),
+            },
+            read_only: self.read_only,
+        };
+
+        if engine.is_replica() && !engine.read_only {
+            tokio::spawn(replica::sync_loop(engine.st.clone()));
+        }
+
+        authority::refresh(&engine.st).await;
+
+        if engine.read_only {
+            return Ok(engine);
+        }
+
+        // Save click counts and history every so often, so a crash doesn't lose much of them
+        tokio::spawn({
+            let st = engine.st.clone();
+            async move {
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(SAVE_INTERVAL) => {}
+                        _ = st.shutdown.cancelled() => return,
+                    }
+                    if let Err(err) = st.clicks.save() {
+                        error!("couldn't save click counts: {err}");
+                    }
+                    if let Err(err) = history::save(&st) {
+                        error!("couldn't save history: {err}");
+                    }
+                }
+            }
+        });
+
+        Ok(engine)
+    }
+}
+
+impl Engine {
+    /// Start configuring an engine
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// Get the current config
+    pub fn config(&self) -> Arc<Config> {
+        self.st.config()
+    }
+
+    /// Reload the config file
+    ///
+    /// Changes to crawl targets, boosts, auth, and rate limits take effect right away, but the
+    /// listen address and TLS settings are only read by [Engine::serve].
+    pub fn reload_config(&self) -> Result<(), Box<dyn Error>> {
+        self.st.reload_config()
+    }
+
+    /// Search the index
+    pub async fn search(
+        &self,
+        q: &str,
+        opts: SearchOptions,
+    ) -> Result<SearchRes, Box<dyn Error + Send + Sync>> {
+        search::search(&self.st, q.to_string(), opts).await
+    }
+
+    /// Number of documents in the index
+    pub fn num_docs(&self) -> u64 {
+        self.st.reader.searcher().num_docs()
+    }
+
+    /// Whether this is a replica, which syncs its index from a primary instead of crawling
+    pub fn is_replica(&self) -> bool {
+        self.config().replica.is_some()
+    }
+
+    /// Whether the index should be crawled: it's empty, or the last crawl was interrupted
+    pub fn needs_crawl(&self) -> bool {
+        !self.read_only && !self.is_replica() && (self.num_docs() == 0 || crawler::interrupted())
+    }
+
+    /// Start crawling in the background
+    ///
+    /// If `target` is given, only targets from that source, or whose start URL contains it,
+    /// are crawled. This returns `false` if a crawl is already running, or this is a replica or
+    /// read-only.
+    pub fn crawl(&self, target: Option<String>) -> bool {
+        !self.read_only
+            && !self.is_replica()
+            && self.st.jobs.start(self.st.clone(), target).is_some()
+    }
+
+    /// Router for the search page and HTTP API
+    ///
+    /// This can be merged into another axum app, which should be served with
+    /// `into_make_service_with_connect_info::<SocketAddr>()` so rate limiting can tell clients
+    /// apart. Or, [Engine::serve] serves it by itself.
+    pub fn router(&self) -> Router {
+        crate::router(self.st.clone())
+    }
+
+    /// Serve the search page and HTTP API on the configured address
+    ///
+    /// This runs until a fatal error is encountered, or the shutdown token is cancelled and
+    /// in-flight requests (and crawls) have finished.
+    pub async fn serve(&self) -> Result<(), Box<dyn Error>> {
+        let shutdown = self.st.shutdown.clone();
+
+        systemd::spawn_watchdog(shutdown.clone());
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown.cancelled().await;
+                systemd::stopping();
+            }
+        });
+
+        server::serve(self.router(), &self.config(), shutdown).await?;
+
+        self.shutdown().await;
+
+        Ok(())
+    }
+
+    /// Stop any running crawl and wait for it to save a checkpoint, save click counts and
+    /// history, and close the index
+    pub async fn shutdown(&self) {
+        self.st.shutdown.cancel();
+        self.st.jobs.wait().await;
+        if self.read_only {
+            return;
+        }
+
+        if let Err(err) = self.st.clicks.save() {
+            error!("couldn't save click counts: {err}");
+        }
+        if let Err(err) = history::save(&self.st) {
+            error!("couldn't save history: {err}");
+        }
+        // Make sure everything is on disk before exiting
+        if let Err(err) = self.st.index.close() {
+            error!("couldn't close the index: {err}");
+        }
+    }
+}
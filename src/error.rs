@@ -0,0 +1,98 @@
+use std::error::Error;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use tera::Context;
+
+use crate::AppState;
+
+/// Error returned by request handlers
+///
+/// This is sent as JSON (`{"error": "..."}`), except on pages, where [render_html] replaces it
+/// with the error template.
+#[derive(Clone, Debug)]
+pub struct AppError {
+    status: StatusCode,
+    message: String,
+}
+impl AppError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "there's nothing here")
+    }
+}
+
+/// Anything that went wrong on our end is a 500
+///
+/// The details are logged, rather than shown to the client.
+impl<E: Into<Box<dyn Error + Send + Sync>>> From<E> for AppError {
+    fn from(err: E) -> Self {
+        error!("error handling request: {}", err.into());
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "something went wrong")
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            error: &'a str,
+        }
+
+        let mut res = (
+            self.status,
+            Json(Body {
+                error: &self.message,
+            }),
+        )
+            .into_response();
+        // Keep the error around, so it can be rendered as a page instead
+        res.extensions_mut().insert(self);
+        res
+    }
+}
+
+/// Fallback for routes that don't exist
+pub async fn not_found() -> AppError {
+    AppError::not_found()
+}
+
+/// Middleware rendering errors with the error template, except on API routes
+pub async fn render_html(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    if path.starts_with("/api/") || path.starts_with("/admin/") {
+        return next.run(req).await;
+    }
+
+    let headers = req.headers().clone();
+    let res = next.run(req).await;
+    let Some(err) = res.extensions().get::<AppError>().cloned() else {
+        return res;
+    };
+
+    let mut ctx = Context::new();
+    ctx.insert("status", &err.status.as_u16());
+    ctx.insert("reason", &err.status.canonical_reason().unwrap_or("Error"));
+    ctx.insert("message", &err.message);
+
+    match st.page_templates(&headers).render("error.html", &ctx) {
+        Ok(page) => (err.status, Html(page)).into_response(),
+        Err(render_err) => {
+            error!("couldn't render the error page: {render_err}");
+            res
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use spider::{
+    packages::scraper::{ElementRef, Html, Selector},
+    url::Url,
+};
+
+use crate::sanitize;
+
+/// Content extracted from a page
+///
+/// Only the title, body, and language are indexed for now. The rest is extracted so it's
+/// available to features that need it.
+#[derive(Clone, Debug, Default)]
+pub struct Extracted {
+    pub title: String,
+    pub body: String,
+    pub lang: String,
+    /// Headings of the page's sections, in order
+    pub sections: Vec<String>,
+    /// Code blocks on the page
+    pub code: Vec<String>,
+    /// Other metadata from `<meta>` tags, like the page's `description` or `og:title`
+    ///
+    /// schema.org metadata from JSON-LD is included too, as `schema:type`, `schema:description`,
+    /// and `schema:image`. Image URLs are made absolute.
+    pub metadata: BTreeMap<String, String>,
+    /// Web pages the page links to, made absolute and without fragments, for ranking
+    pub links: Vec<String>,
+}
+impl Extracted {
+    /// The page's description, from its `description` or `og:description` meta tag
+    pub fn description(&self) -> Option<&str> {
+        self.first_of(&["description", "og:description", "schema:description"])
+    }
+
+    /// What kind of page this is, like `article` or `TechArticle`
+    pub fn page_type(&self) -> Option<&str> {
+        self.first_of(&["og:type", "schema:type"])
+    }
+
+    /// URL of the page's preview image
+    pub fn image(&self) -> Option<&str> {
+        self.first_of(&["og:image", "schema:image"])
+    }
+
+    /// Get the first of some metadata keys that's set to something
+    fn first_of(&self, keys: &[&str]) -> Option<&str> {
+        keys.iter()
+            .filter_map(|key| self.metadata.get(*key))
+            .map(|value| value.trim())
+            .find(|value| !value.is_empty())
+    }
+}
+
+/// Turns a page's HTML into the content that's indexed
+///
+/// The [default extractor](SelectorExtractor) works well enough for most sites, but sites with
+/// unusual layouts can have their own, registered with [Extractors::register].
+pub trait Extractor: Send + Sync {
+    fn extract(&self, url: &Url, html: &Html) -> Extracted;
+}
+
+/// The default extractor, which picks out content with CSS selectors
+pub struct SelectorExtractor {
+    title: Selector,
+    body: Selector,
+    sections: Selector,
+    code: Selector,
+    meta: Selector,
+    json_ld: Selector,
+    links: Selector,
+}
+impl SelectorExtractor {
+    /// Use other selectors for the body text, keeping the defaults for everything else
+    ///
+    /// This is enough for sites that keep their content in a particular element, like `main`.
+    pub fn with_body(body: &str) -> Result<Self, String> {
+        Ok(Self {
+            body: Selector::parse(body).map_err(|err| err.to_string())?,
+            ..Self::default()
+        })
+    }
+}
+impl Default for SelectorExtractor {
+    fn default() -> Self {
+        Self {
+            title: Selector::parse("title").unwrap(),
+            body: Selector::parse("p, h1, h2, h3, h4").unwrap(),
+            sections: Selector::parse("h1, h2, h3").unwrap(),
+            code: Selector::parse("pre").unwrap(),
+            meta: Selector::parse("meta[name][content], meta[property][content]").unwrap(),
+            json_ld: Selector::parse(r#"script[type="application/ld+json"]"#).unwrap(),
+            links: Selector::parse("a[href]").unwrap(),
+        }
+    }
+}
+impl Extractor for SelectorExtractor {
+    fn extract(&self, url: &Url, html: &Html) -> Extracted {
+        let text = |elem: ElementRef| elem.text().collect::<Vec<_>>().join(" ");
+
+        let body = html
+            .select(&self.body)
+            .map(text)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let title = html
+            .select(&self.title)
+            .next()
+            .map(|elem| sanitize::text(&elem.text().collect::<String>()))
+            .filter(|title| !title.is_empty())
+            .unwrap_or(url.to_string());
+
+        let sections = html
+            .select(&self.sections)
+            .map(|elem| text(elem).trim().to_string())
+            .filter(|heading| !heading.is_empty())
+            .collect();
+
+        let code = html
+            .select(&self.code)
+            .map(|elem| elem.text().collect())
+            .collect();
+
+        let mut metadata: BTreeMap<_, _> = html
+            .select(&self.meta)
+            .filter_map(|elem| {
+                // OpenGraph tags use `property` instead of `name`
+                let elem = elem.value();
+                let name = elem
+                    .attr("name")
+                    .or(elem.attr("property"))?
+                    .to_ascii_lowercase();
+                let content = elem.attr("content")?.to_string();
+                Some((name, content))
+            })
+            .collect();
+
+        if let Some(schema) = html
+            .select(&self.json_ld)
+            .filter_map(|elem| serde_json::from_str(&elem.text().collect::<String>()).ok())
+            .find_map(|json| schema_item(&json).cloned())
+        {
+            let mut add = |key: &str, value: Option<&str>| {
+                if let Some(value) = value {
+                    metadata.entry(key.to_string()).or_insert(value.to_string());
+                }
+            };
+            add("schema:type", schema_str(&schema["@type"]));
+            add("schema:description", schema_str(&schema["description"]));
+            add("schema:image", schema_image(&schema["image"]));
+        }
+
+        // Preview images are often given relative to the page
+        for key in ["og:image", "schema:image"] {
+            if let Some(image) = metadata.remove(key) {
+                if let Ok(image) = url.join(image.trim()) {
+                    if matches!(image.scheme(), "http" | "https") {
+                        metadata.insert(key.to_string(), image.to_string());
+                    }
+                }
+            }
+        }
+
+        let mut links: Vec<_> = html
+            .select(&self.links)
+            .filter_map(|elem| {
+                let mut link = url.join(elem.value().attr("href")?.trim()).ok()?;
+                link.set_fragment(None);
+                (matches!(link.scheme(), "http" | "https") && link != *url)
+                    .then(|| link.to_string())
+            })
+            .collect();
+        links.sort();
+        links.dedup();
+
+        let lang = detect_lang(html, &body);
+
+        Extracted {
+            title,
+            body,
+            lang,
+            sections,
+            code,
+            metadata,
+            links,
+        }
+    }
+}
+
+/// Find the main schema.org item in a JSON-LD block
+///
+/// A block can be a single item, a list of them, or a `@graph` of them. The first item with a
+/// `@type` is used.
+fn schema_item(json: &serde_json::Value) -> Option<&serde_json::Value> {
+    match json {
+        serde_json::Value::Array(items) => items.iter().find_map(schema_item),
+        serde_json::Value::Object(item) if item.contains_key("@type") => Some(json),
+        serde_json::Value::Object(item) => item.get("@graph").and_then(schema_item),
+        _ => None,
+    }
+}
+
+/// Get a schema.org property that's a string, or a list of strings (taking the first)
+fn schema_str(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Array(values) => values.iter().find_map(schema_str),
+        _ => None,
+    }
+}
+
+/// Get a schema.org image's URL, whether it's given directly or as an `ImageObject`
+fn schema_image(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::Array(values) => values.iter().find_map(schema_image),
+        serde_json::Value::Object(image) => image.get("url").and_then(schema_str),
+        _ => schema_str(value),
+    }
+}
+
+/// The extractor to use for each domain
+#[derive(Clone)]
+pub struct Extractors {
+    default: Arc<dyn Extractor>,
+    domains: BTreeMap<String, Arc<dyn Extractor>>,
+}
+impl Default for Extractors {
+    fn default() -> Self {
+        Self {
+            default: Arc::new(SelectorExtractor::default()),
+            domains: BTreeMap::new(),
+        }
+    }
+}
+impl Extractors {
+    /// Use `extractor` for pages on `domain` and its subdomains
+    pub fn register(&mut self, domain: &str, extractor: impl Extractor + 'static) {
+        self.domains
+            .insert(domain.to_ascii_lowercase(), Arc::new(extractor));
+    }
+
+    /// Get the extractor for a page
+    ///
+    /// The most specific registered domain wins, so `docs.example.com` can have a different
+    /// extractor than the rest of `example.com`.
+    pub fn get(&self, url: &Url) -> &dyn Extractor {
+        let host = url.host_str().unwrap_or_default();
+
+        self.domains
+            .iter()
+            .filter(|(domain, _)| {
+                host == domain.as_str()
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+            .map_or(self.default.as_ref(), |(_, extractor)| extractor.as_ref())
+    }
+
+    /// Parse a page's HTML and extract it with the extractor for its domain
+    pub fn extract(&self, url: &Url, html: &str) -> Extracted {
+        self.get(url).extract(url, &Html::parse_document(html))
+    }
+}
+
+/// Detect the language of a page as an ISO 639-1 code (e.g. `en`)
+///
+/// The page's own `lang` attribute is preferred, since most documentation sites set it.
+/// Otherwise, the language is guessed from the body text. Pages where neither works are
+/// marked `und` (undetermined).
+pub fn detect_lang(html: &Html, body: &str) -> String {
+    html.root_element()
+        .value()
+        .attr("lang")
+        .and_then(|lang| lang.split(['-', '_']).next())
+        .filter(|lang| !lang.is_empty())
+        .map(|lang| lang.to_ascii_lowercase())
+        .unwrap_or_else(|| guess_lang(body))
+}
+
+/// Guess the language of some text as an ISO 639-1 code, or `und` if it's unclear
+pub fn guess_lang(text: &str) -> String {
+    whatlang::detect(text)
+        .and_then(|info| isolang::Language::from_639_3(info.lang().code()))
+        .and_then(|lang| lang.to_639_1())
+        .map_or_else(|| "und".to_string(), str::to_string)
+}
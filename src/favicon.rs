@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    path::{Path as FsPath, PathBuf},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+use spider::{reqwest, url::Url};
+use tantivy::{collector::Count, query::RegexQuery};
+
+use crate::{error::AppError, index::INDEX_DIR, AppState};
+
+/// Largest favicon that will be cached
+const MAX_FAVICON_SIZE: usize = 100_000;
+/// How long browsers can cache favicons for
+const CACHE_MAX_AGE: u64 = 7 * 24 * 60 * 60;
+
+/// Get the URL of the favicon for a page's domain
+pub fn url_for(page: &str) -> Option<String> {
+    let url = Url::parse(page).ok()?;
+    Some(format!("/favicon/{}", url.host_str()?))
+}
+
+/// Where a domain's favicon is cached
+///
+/// An empty file means the domain has no favicon, so it isn't fetched again.
+fn cache_path(domain: &str) -> PathBuf {
+    FsPath::new(INDEX_DIR).join("favicons").join(domain)
+}
+
+/// Work out the content type of an icon from its first few bytes
+///
+/// SVGs aren't accepted, since they can contain scripts.
+fn icon_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if data.starts_with(&[0, 0, 1, 0]) {
+        Some("image/x-icon")
+    } else if data.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        Some("image/jpeg")
+    } else {
+        None
+    }
+}
+
+/// Fetch a domain's favicon from the usual location
+async fn fetch(domain: &str) -> Option<Vec<u8>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .ok()?;
+
+    let res = client
+        .get(format!("https://{domain}/favicon.ico"))
+        .send()
+        .await
+        .ok()?;
+    if !res.status().is_success() {
+        return None;
+    }
+
+    let data = res.bytes().await.ok()?;
+    (data.len() <= MAX_FAVICON_SIZE && icon_type(&data).is_some()).then(|| data.to_vec())
+}
+
+/// Check whether any indexed page is on a domain
+fn is_indexed(st: &AppState, domain: &str) -> Result<bool, AppError> {
+    // Domains are checked to only have letters, digits, dots, and dashes, so only the dots
+    // need escaping
+    let pattern = format!("https?://{}/.*", domain.replace('.', "\\."));
+    let query = RegexQuery::from_pattern(&pattern, st.schema.get_field("id")?)?;
+
+    Ok(st.reader.searcher().search(&query, &Count)? > 0)
+}
+
+/// `GET /favicon/:domain`: the favicon of an indexed domain
+///
+/// Favicons are fetched the first time they're asked for, and cached alongside the index.
+pub async fn favicon(
+    State(st): State<AppState>,
+    Path(domain): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let domain = domain.to_ascii_lowercase();
+    if domain.is_empty()
+        || domain.starts_with('.')
+        || !domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    {
+        return Err(AppError::not_found());
+    }
+
+    let path = cache_path(&domain);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        // Only fetch icons for domains we've indexed, so this can't be used to make us
+        // request arbitrary sites
+        Err(_) if is_indexed(&st, &domain)? => {
+            let data = fetch(&domain).await.unwrap_or_default();
+            if data.is_empty() {
+                debug!("{domain} has no favicon");
+            }
+
+            fs::create_dir_all(path.parent().unwrap_or(&path))?;
+            fs::write(&path, &data)?;
+            data
+        }
+        Err(_) => return Err(AppError::not_found()),
+    };
+
+    let Some(content_type) = icon_type(&data) else {
+        return Err(AppError::not_found());
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CACHE_CONTROL,
+                format!("public, max-age={CACHE_MAX_AGE}"),
+            ),
+        ],
+        data,
+    ))
+}
@@ -0,0 +1,77 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use tantivy::{
+    collector::TopDocs, query::AllQuery, schema::Value, DateTime, Order, TantivyDocument,
+};
+use tera::Context;
+use time::format_description::well_known::Rfc3339;
+
+use crate::{base_url, crawler, error::AppError, AppState};
+
+/// Number of pages listed in the feed
+const FEED_ENTRIES: usize = 50;
+
+#[derive(Serialize)]
+struct Entry {
+    url: String,
+    title: String,
+    source: String,
+    /// When the page was crawled, as an RFC 3339 timestamp
+    indexed_at: String,
+}
+
+pub fn rfc3339(date: DateTime) -> String {
+    date.into_utc().format(&Rfc3339).unwrap_or_default()
+}
+
+/// `GET /feed.xml`: Atom feed of the most recently indexed pages
+pub async fn feed(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let url = st.schema.get_field("url")?;
+    let title = st.schema.get_field("title")?;
+    let source = st.schema.get_field("source")?;
+
+    let searcher = st.reader.searcher();
+    let newest = searcher.search(
+        &AllQuery,
+        &TopDocs::with_limit(FEED_ENTRIES)
+            .order_by_fast_field::<DateTime>("indexed_at", Order::Desc),
+    )?;
+
+    let mut entries = Vec::with_capacity(newest.len());
+    for (indexed_at, doc_addr) in newest {
+        let doc = searcher.doc::<TantivyDocument>(doc_addr)?;
+        let text = |field| {
+            doc.get_first(field)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        entries.push(Entry {
+            url: text(url),
+            title: text(title),
+            source: text(source),
+            indexed_at: rfc3339(indexed_at),
+        });
+    }
+
+    let mut ctx = Context::new();
+    ctx.insert("base_url", &base_url(&st.config(), &headers));
+    ctx.insert(
+        "updated",
+        &entries
+            .first()
+            .map_or_else(|| rfc3339(crawler::now()), |e| e.indexed_at.clone()),
+    );
+    ctx.insert("entries", &entries);
+
+    let body = st.templates().render("feed.xml", &ctx)?;
+
+    Ok(([(header::CONTENT_TYPE, "application/atom+xml")], body))
+}
@@ -0,0 +1,95 @@
+use regex::{Regex, RegexBuilder};
+use spider::url::Url;
+
+/// Rules for which pages can be crawled and shown in results
+///
+/// Pages matching a block rule are never crawled or shown. If there are any allow rules, only
+/// pages matching one of them are. Since results are filtered too, blocking a page takes effect
+/// right away, without waiting for a recrawl.
+#[derive(Deserialize, Default, Clone)]
+#[serde(try_from = "FilterConfig")]
+pub struct UrlFilter {
+    block: Rules,
+    allow: Rules,
+}
+
+/// The rules as they're written in the config
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FilterConfig {
+    /// Domain globs, like `*.example.com`
+    block_domains: Vec<String>,
+    /// Regexes matched against the whole URL
+    block_urls: Vec<String>,
+    allow_domains: Vec<String>,
+    allow_urls: Vec<String>,
+}
+
+#[derive(Default, Clone)]
+struct Rules {
+    domains: Vec<Regex>,
+    urls: Vec<Regex>,
+}
+impl Rules {
+    fn new(domains: &[String], urls: &[String]) -> Result<Self, regex::Error> {
+        Ok(Self {
+            domains: domains
+                .iter()
+                .map(|glob| domain_regex(glob))
+                .collect::<Result<_, _>>()?,
+            urls: urls
+                .iter()
+                .map(|re| Regex::new(re))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.urls.is_empty()
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default();
+        self.domains.iter().any(|re| re.is_match(host))
+            || self.urls.iter().any(|re| re.is_match(url.as_str()))
+    }
+}
+
+/// Turn a domain glob into a regex
+///
+/// `*` matches anything, so `*.example.com` matches every subdomain of `example.com` (but not
+/// `example.com` itself).
+fn domain_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let pattern = regex::escape(glob).replace(r"\*", ".*");
+    RegexBuilder::new(&format!("^{pattern}$"))
+        .case_insensitive(true)
+        .build()
+}
+
+impl TryFrom<FilterConfig> for UrlFilter {
+    type Error = regex::Error;
+
+    fn try_from(config: FilterConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            block: Rules::new(&config.block_domains, &config.block_urls)?,
+            allow: Rules::new(&config.allow_domains, &config.allow_urls)?,
+        })
+    }
+}
+
+impl UrlFilter {
+    /// Check whether a page can be crawled and shown
+    pub fn allows(&self, url: &Url) -> bool {
+        !self.block.matches(url) && (self.allow.is_empty() || self.allow.matches(url))
+    }
+
+    /// Check whether a page can be shown, given its URL from the index
+    ///
+    /// URLs that can't be parsed are only allowed if there are no rules at all.
+    pub fn allows_str(&self, url: &str) -> bool {
+        match Url::parse(url) {
+            Ok(url) => self.allows(&url),
+            Err(_) => self.block.is_empty() && self.allow.is_empty(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use spider::url::Url;
+
+use crate::crawler::{self, CrawlTarget, ScrapedPage};
+
+/// Crawl from saved pages instead of the network
+///
+/// This makes crawls deterministic, for developing extraction and ranking changes and for
+/// testing without hitting real sites.
+#[derive(Deserialize, Clone)]
+pub struct FixtureConfig {
+    /// Directory the pages are saved in
+    pub dir: PathBuf,
+    #[serde(default)]
+    pub mode: FixtureMode,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FixtureMode {
+    /// Read pages from the directory, without touching the network
+    #[default]
+    Replay,
+    /// Crawl normally, and save every crawled page to the directory
+    Record,
+}
+
+/// A page saved in an archive, one per line
+#[derive(Serialize, Deserialize)]
+struct SavedPage {
+    url: String,
+    html: String,
+}
+
+/// Path of the archive a target's pages are recorded in
+///
+/// The name comes from the target's start URL, so each target gets its own archive even when
+/// several share a source.
+fn archive_path(dir: &Path, target: &CrawlTarget) -> PathBuf {
+    let url = target
+        .url
+        .split_once("://")
+        .map_or(&*target.url, |(_, rest)| rest);
+    let name: String = url
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    dir.join(format!("{name}.jsonl"))
+}
+
+/// Save the pages crawled for a target, replacing any that were saved before
+///
+/// Only pages that were fetched successfully are saved.
+pub fn record(
+    config: &FixtureConfig,
+    target: &CrawlTarget,
+    pages: &[ScrapedPage],
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&config.dir)?;
+
+    let path = archive_path(&config.dir, target);
+    let mut w = BufWriter::new(File::create(&path)?);
+    let mut count = 0;
+    for page in pages.iter().filter(|page| page.ok()) {
+        serde_json::to_writer(
+            &mut w,
+            &SavedPage {
+                url: page.url.to_string(),
+                html: page.html.clone(),
+            },
+        )?;
+        w.write_all(b"\n")?;
+        count += 1;
+    }
+    w.flush()?;
+
+    info!(url = %target.url, pages = count, path = %path.display(), "recorded fixtures");
+    Ok(())
+}
+
+/// Load the saved pages for a target, sorted by URL
+///
+/// Pages come from the target's archive, if it was recorded. Otherwise, they're read from a
+/// mirror of the site in the directory, like the one `wget --mirror` makes, where
+/// `<dir>/docs.python.org/3.13/index.html` is `https://docs.python.org/3.13/index.html`.
+/// Like a real crawl, only pages on the start URL's host that the target includes are kept.
+pub fn replay(
+    config: &FixtureConfig,
+    target: &CrawlTarget,
+) -> Result<Vec<ScrapedPage>, Box<dyn Error>> {
+    let start = Url::parse(&target.url)?;
+    let host = start.host_str().unwrap_or_default();
+
+    let mut pages = Vec::new();
+    match File::open(archive_path(&config.dir, target)) {
+        Ok(file) => {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let page: SavedPage = serde_json::from_str(&line)?;
+                pages.push((Url::parse(&page.url)?, page.html));
+            }
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let root = config.dir.join(host);
+            if root.is_dir() {
+                read_mirror(&root, &root, &start, &mut pages)?;
+            }
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    pages.retain(|(url, _)| url.host_str() == Some(host) && target.is_good_url(url));
+    pages.sort_by(|a, b| a.0.cmp(&b.0));
+    pages.dedup_by(|a, b| a.0 == b.0);
+    pages.truncate(crawler::MAX_PAGES);
+
+    if pages.is_empty() {
+        warn!(url = %target.url, dir = %config.dir.display(), "no fixtures found for target");
+    }
+
+    Ok(pages
+        .into_iter()
+        .map(|(url, html)| ScrapedPage {
+            url,
+            html,
+            status: 200,
+            etag: None,
+            last_modified: None,
+        })
+        .collect())
+}
+
+/// Read every HTML page under `dir`, a directory in a mirror of the site rooted at `root`
+fn read_mirror(
+    root: &Path,
+    dir: &Path,
+    start: &Url,
+    pages: &mut Vec<(Url, String)>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_mirror(root, &path, start, pages)?;
+            continue;
+        }
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "htm")
+        {
+            continue;
+        }
+
+        let Ok(rel) = path.strip_prefix(root) else {
+            continue;
+        };
+        let rel: Vec<_> = rel.iter().map(|part| part.to_string_lossy()).collect();
+        let url = start.join(&format!("/{}", rel.join("/")))?;
+        pages.push((url, String::from_utf8_lossy(&fs::read(&path)?).into_owned()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use axum::{extract::State, http::StatusCode, Json};
+use tantivy::{collector::Count, query::AllQuery};
+
+use crate::AppState;
+
+#[derive(Serialize)]
+pub struct Readiness {
+    ready: bool,
+    /// Number of documents in the index, if it could be searched
+    docs: Option<usize>,
+    /// Whether the embedding model could run inference when the server started
+    model: bool,
+}
+
+/// `GET /healthz`: the process is alive and serving requests
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// `GET /readyz`: the index and model are loaded and working
+pub async fn readyz(State(st): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let docs = st.reader.searcher().search(&AllQuery, &Count).ok();
+
+    // Running inference on every probe would compete with searches, so this is checked once
+    // on startup
+    let model = st.se.is_ready();
+
+    let ready = docs.is_some() && model;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(Readiness { ready, docs, model }))
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{Html, Redirect},
+};
+use tantivy::DateTime;
+use tera::Context;
+
+use crate::{browser, error::AppError, feed::rfc3339, store::JsonStore, AppState};
+
+/// Query history, kept for each browser
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Remember what each browser searched for, and show it at `/history`
+    pub enabled: bool,
+    /// Most searches remembered for each browser
+    pub max_entries: usize,
+    /// Days searches are remembered for
+    pub retention_days: u64,
+}
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: 100,
+            retention_days: 30,
+        }
+    }
+}
+
+/// A search in a browser's history
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub query: String,
+    pub lang: String,
+    /// When it was searched for, in seconds since the Unix epoch
+    pub searched_at: u64,
+}
+
+/// Searches for each browser, newest first, keyed by the ID in the browser's cookie
+pub type History = JsonStore<HashMap<String, Vec<HistoryEntry>>>;
+
+/// Load the query history
+pub fn load() -> Result<History, Box<dyn Error>> {
+    JsonStore::load("history.json")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Most browsers history is kept for, so clients making up IDs can't fill the disk
+const MAX_BROWSERS: usize = 10_000;
+
+/// Record a search in the browser's history, if history is enabled
+///
+/// Searches from browsers without an ID (see [browser::id]) aren't recorded, since clients that
+/// never send cookies back (like crawlers and `curl`) would each add a browser whose history
+/// nobody can see. The history is saved every so often, not on every search.
+pub fn record(st: &AppState, headers: &HeaderMap, query: &str, lang: &str) {
+    let config = st.config();
+    if !config.history.enabled {
+        return;
+    }
+    let Some(id) = browser::id(headers) else {
+        return;
+    };
+
+    let now = now();
+    st.history.change(|history| {
+        if !history.contains_key(&id) && history.len() >= MAX_BROWSERS {
+            // Make room by forgetting the browser that searched least recently
+            let stalest = history
+                .iter()
+                .min_by_key(|(_, entries)| entries.first().map_or(0, |e| e.searched_at))
+                .map(|(id, _)| id.clone());
+            if let Some(stalest) = stalest {
+                history.remove(&stalest);
+            }
+        }
+
+        let entries = history.entry(id).or_default();
+        // Searching for something again moves it to the top
+        entries.retain(|e| !(e.query == query && e.lang == lang));
+        entries.insert(
+            0,
+            HistoryEntry {
+                query: query.to_string(),
+                lang: lang.to_string(),
+                searched_at: now,
+            },
+        );
+        entries.truncate(config.history.max_entries);
+    });
+}
+
+/// Forget old searches and browsers that haven't searched in a while, and save the history
+pub fn save(st: &AppState) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let oldest = now().saturating_sub(st.config().history.retention_days * 24 * 60 * 60);
+    let expired = st.history.read(|history| {
+        history
+            .values()
+            .any(|entries| entries.iter().any(|e| e.searched_at < oldest))
+    });
+    if expired {
+        st.history.change(|history| {
+            for entries in history.values_mut() {
+                entries.retain(|e| e.searched_at >= oldest);
+            }
+            history.retain(|_, entries| !entries.is_empty());
+        });
+    }
+
+    st.history.save()
+}
+
+/// `GET /history`: list the browser's recent searches
+pub async fn history_page(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Html<String>, AppError> {
+    if !st.config().history.enabled {
+        return Err(AppError::not_found());
+    }
+
+    #[derive(Serialize)]
+    struct Entry {
+        query: String,
+        lang: String,
+        searched_at: String,
+    }
+
+    let entries: Vec<_> = browser::id(&headers)
+        .and_then(|id| st.history.read(|history| history.get(&id).cloned()))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| Entry {
+            query: e.query,
+            lang: e.lang,
+            searched_at: rfc3339(DateTime::from_timestamp_secs(e.searched_at as i64)),
+        })
+        .collect();
+
+    let mut ctx = Context::new();
+    ctx.insert("entries", &entries);
+
+    Ok(Html(
+        st.page_templates(&headers).render("history.html", &ctx)?,
+    ))
+}
+
+/// `POST /history/clear`: forget the browser's searches
+pub async fn clear(State(st): State<AppState>, headers: HeaderMap) -> Result<Redirect, AppError> {
+    if let Some(id) = browser::id(&headers) {
+        st.history.update(|history| history.remove(&id))?;
+    }
+
+    Ok(Redirect::to("/history"))
+}
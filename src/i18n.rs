@@ -0,0 +1,211 @@
+use std::{collections::HashMap, error::Error, fs, path::Path as FsPath, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use rust_embed::RustEmbed;
+use spider::url::Url;
+use tera::{Tera, Value};
+
+use crate::AppState;
+
+/// Message catalogs compiled into the binary, one `<locale>.toml` per locale
+#[derive(RustEmbed)]
+#[folder = "locales/"]
+struct Catalogs;
+
+/// Locale used when the browser doesn't ask for one we have, and for missing messages
+pub const DEFAULT_LOCALE: &str = "en";
+/// Cookie a locale picked on the page is kept in
+const COOKIE: &str = "mini_search_locale";
+/// How long the cookie lasts, in seconds
+const COOKIE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/// Messages for the UI, keyed by locale and then by message
+pub struct Locales {
+    catalogs: HashMap<String, HashMap<String, String>>,
+}
+
+/// Load the message catalogs
+///
+/// Catalogs in `locales/` on disk override the ones compiled into the binary, the same as
+/// templates in `views/` do.
+pub fn load() -> Result<Locales, Box<dyn Error>> {
+    let mut files = Vec::new();
+    if FsPath::new("locales").is_dir() {
+        info!("loading message catalogs from locales/");
+        for entry in fs::read_dir("locales")? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                files.push((
+                    path.to_string_lossy().into_owned(),
+                    fs::read_to_string(&path)?,
+                ));
+            }
+        }
+    } else {
+        for name in Catalogs::iter() {
+            if let Some(file) = Catalogs::get(&name) {
+                files.push((
+                    name.to_string(),
+                    String::from_utf8_lossy(&file.data).into_owned(),
+                ));
+            }
+        }
+    }
+
+    let mut catalogs = HashMap::new();
+    for (name, s) in files {
+        let Some(locale) = FsPath::new(&name).file_stem() else {
+            continue;
+        };
+        let catalog: HashMap<String, String> =
+            toml::from_str(&s).map_err(|err| format!("couldn't load {name}: {err}"))?;
+        catalogs.insert(locale.to_string_lossy().to_ascii_lowercase(), catalog);
+    }
+
+    Ok(Locales { catalogs })
+}
+
+impl Locales {
+    /// Get a message, falling back to the default locale and then to the key itself
+    fn message<'a>(&'a self, locale: &str, key: &'a str) -> &'a str {
+        [locale, DEFAULT_LOCALE]
+            .iter()
+            .find_map(|locale| self.catalogs.get(*locale)?.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// Pick the locale to show a request's page in
+    ///
+    /// A locale picked on the page (and kept in a cookie) wins, then the first language in
+    /// `Accept-Language` there's a catalog for. A regional language like `de-AT` falls back to
+    /// its base language (`de`).
+    pub fn negotiate(&self, headers: &HeaderMap) -> String {
+        let picked = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|cookies| cookies.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, _)| *name == COOKIE)
+            .map(|(_, locale)| locale.to_ascii_lowercase());
+        if let Some(locale) = picked.filter(|locale| self.catalogs.contains_key(locale)) {
+            return locale;
+        }
+
+        let mut wanted: Vec<(f32, String)> = headers
+            .get_all(header::ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|langs| langs.to_str().ok())
+            .flat_map(|langs| langs.split(','))
+            .filter_map(|lang| {
+                let mut parts = lang.split(';');
+                let tag = parts.next()?.trim().to_ascii_lowercase();
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                (!tag.is_empty() && tag != "*" && q > 0.0).then_some((q, tag))
+            })
+            .collect();
+        // Sorting is stable, so languages with the same weight keep their order
+        wanted.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        wanted
+            .into_iter()
+            .find_map(|(_, tag)| {
+                let base = tag.split('-').next().unwrap_or_default();
+                [tag.as_str(), base]
+                    .into_iter()
+                    .find(|locale| self.catalogs.contains_key(*locale))
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+    }
+}
+
+/// Give templates the messages for `locale`
+///
+/// This registers a few functions for templates:
+/// - `t(key="no_results")` gets a message. Other arguments fill in placeholders in it, so
+///   `t(key="stats_total", total=total)` replaces `{total}`.
+/// - `locale()` gets the locale's code, like `en`.
+/// - `locales()` lists every locale with a catalog, as `code` and `name`.
+pub fn localize(tera: &mut Tera, locales: &Arc<Locales>, locale: String) {
+    tera.register_function("t", {
+        let locales = locales.clone();
+        let locale = locale.clone();
+        move |args: &HashMap<String, Value>| {
+            let key = args
+                .get("key")
+                .and_then(Value::as_str)
+                .ok_or_else(|| tera::Error::msg("t() needs a `key` argument"))?;
+
+            let mut message = locales.message(&locale, key).to_string();
+            for (name, value) in args.iter().filter(|(name, _)| *name != "key") {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                };
+                message = message.replace(&format!("{{{name}}}"), &value);
+            }
+
+            Ok(Value::String(message))
+        }
+    });
+
+    tera.register_function("locale", move |_: &HashMap<String, Value>| {
+        Ok(Value::String(locale.clone()))
+    });
+
+    let mut list: Vec<_> = locales
+        .catalogs
+        .iter()
+        .map(|(code, catalog)| {
+            let name = catalog.get("locale_name").unwrap_or(code);
+            serde_json::json!({ "code": code, "name": name })
+        })
+        .collect();
+    list.sort_by(|a, b| a["code"].as_str().cmp(&b["code"].as_str()));
+    tera.register_function("locales", move |_: &HashMap<String, Value>| {
+        Ok(Value::Array(list.clone()))
+    });
+}
+
+#[derive(Deserialize)]
+pub struct LocaleForm {
+    locale: String,
+}
+
+/// `POST /locale`: pick the locale to show pages in, and go back to the page the form was on
+pub async fn set_locale(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<LocaleForm>,
+) -> impl IntoResponse {
+    // Only go back to a path on this instance, so the form can't send people elsewhere
+    let back = headers
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| Url::parse(referer).ok())
+        .filter(|url| !url.path().starts_with("//"))
+        .map(|url| match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        })
+        .unwrap_or_else(|| "/".to_string());
+
+    let mut res = Redirect::to(&back).into_response();
+    // Only locales with a catalog make it into the cookie
+    let locale = form.locale.to_ascii_lowercase();
+    if st.locales.catalogs.contains_key(&locale) {
+        let cookie = format!("{COOKIE}={locale}; Path=/; Max-Age={COOKIE_MAX_AGE}; SameSite=Lax");
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            res.headers_mut().insert(header::SET_COOKIE, cookie);
+        }
+    }
+    res
+}
@@ -1,36 +1,100 @@
-use std::{error::Error, fs::create_dir_all};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs::create_dir_all,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
 use tantivy::{
-    query::QueryParser,
-    schema::{Schema, FAST, STORED, TEXT},
+    collector::{Count, TopDocs},
+    query::{QueryParser, TermQuery},
+    schema::{IndexRecordOption, Schema, FAST, INDEXED, STORED, STRING, TEXT},
     store::{Compressor, ZstdCompressor},
-    Index, IndexReader, IndexSettings, IndexWriter,
+    Index, IndexMeta, IndexReader, IndexSettings, IndexWriter, TantivyDocument, Term,
 };
 use tokio::runtime::Handle as TokioRtHandle;
 
+/// Directory the search index (and other state) is stored in
+pub const INDEX_DIR: &str = "mini-search-index";
+/// Least heap Tantivy allows each indexing thread
+const MIN_HEAP_PER_THREAD: usize = 15_000_000;
+/// Most heap Tantivy allows each indexing thread (a bit under its 4 GB limit)
+const MAX_HEAP_PER_THREAD: usize = 4_000_000_000;
+/// Most indexing threads Tantivy uses by default
+const MAX_WRITER_THREADS: usize = 8;
+/// Error for writing to an index that was opened read-only or closed
+const CLOSED: &str = "the index is read-only or closed";
+
 pub struct SearchIndex {
     schema: Schema,
-    index: Index,
     reader: IndexReader,
     parser: QueryParser,
+    /// There can only be one writer, so it's shared by crawls and the indexing API
+    ///
+    /// There's none if the index was opened read-only, and it's taken out when the index is
+    /// closed.
+    writer: Mutex<Option<IndexWriter>>,
+    /// Documents added since the last commit, see [SearchIndex::stage]
+    staged: AtomicUsize,
 }
 impl SearchIndex {
     /// Open the search index (or initialize it, if it doesn't already exist)
-    pub async fn new() -> Result<Self, Box<dyn Error>> {
+    ///
+    /// The writer buffers up to `writer_heap` bytes of documents before flushing them to disk.
+    pub async fn new(writer_heap: usize) -> Result<Self, Box<dyn Error>> {
+        Self::open(Some(writer_heap), true).await
+    }
+    /// Open the search index for reading only
+    ///
+    /// This doesn't take the index's writer lock, so it works while a server has the index open.
+    /// Anything that writes to it returns an error.
+    pub async fn read_only() -> Result<Self, Box<dyn Error>> {
+        Self::open(None, false).await
+    }
+    /// Open the search index for a replica (or initialize it, if it doesn't already exist)
+    ///
+    /// Replicas sync the index from the primary rather than writing to it, so there's no writer
+    /// to commit over the synced `meta.json`.
+    pub async fn replica() -> Result<Self, Box<dyn Error>> {
+        Self::open(None, true).await
+    }
+    /// Open the search index, with a writer with `writer_heap` bytes of heap if there is one
+    ///
+    /// The index is created if it doesn't exist and `create` is set.
+    async fn open(writer_heap: Option<usize>, create: bool) -> Result<Self, Box<dyn Error>> {
         let mut schema = Schema::builder();
 
         let _url = schema.add_text_field("url", TEXT | FAST | STORED);
+        // The exact URL, for finding a document again to replace or delete it
+        let _id = schema.add_text_field("id", STRING | STORED);
         let title = schema.add_text_field("title", TEXT | FAST | STORED);
         let body = schema.add_text_field("body", TEXT | FAST | STORED);
         let _embedding = schema.add_bytes_field("embedding", FAST | STORED);
+        // Where the page is on its site, like `docs.python.org › 3.13 › library`
+        let _breadcrumb = schema.add_text_field("breadcrumb", STORED);
+        // The page's meta description, for when a snippet can't be made from the body
+        let _description = schema.add_text_field("description", STORED);
+        // OpenGraph or schema.org type and preview image, for richer results
+        let _page_type = schema.add_text_field("page_type", STORED);
+        let _image = schema.add_text_field("image", STORED);
+        let _lang = schema.add_text_field("lang", STRING | FAST | STORED);
+        let _source = schema.add_text_field("source", STRING | FAST | STORED);
+        // When the page was crawled, for the feed
+        let _indexed_at = schema.add_date_field("indexed_at", INDEXED | FAST | STORED);
 
         let schema = schema.build();
 
-        let mut index = match Index::open_in_dir("mini-search-index") {
+        let mut index = match Index::open_in_dir(INDEX_DIR) {
             Ok(index) => index,
+            Err(err) if !create => {
+                return Err(format!("couldn't open {INDEX_DIR}: {err}").into());
+            }
             Err(_) => {
                 warn!("no existing index found, creating one");
-                create_dir_all("mini-search-index").unwrap();
+                create_dir_all(INDEX_DIR).unwrap();
                 Index::builder()
                     .schema(schema.clone())
                     .settings(IndexSettings {
@@ -39,30 +103,176 @@ impl SearchIndex {
                         }),
                         ..Default::default()
                     })
-                    .create_in_dir("mini-search-index")
+                    .create_in_dir(INDEX_DIR)
                     .unwrap()
             }
         };
 
+        // An index created by an older version won't have all of the fields we need
+        let index_schema = index.schema();
+        if let Some((_, missing)) = schema
+            .fields()
+            .find(|(_, entry)| index_schema.get_field(entry.name()).is_err())
+        {
+            return Err(format!(
+                "existing index has no `{}` field, delete mini-search-index and recrawl",
+                missing.name()
+            )
+            .into());
+        }
+
         // Use as many threads as Tokio is using, since it gets that from num_cpu
-        index.set_multithread_executor(TokioRtHandle::current().metrics().num_workers())?;
+        let threads = TokioRtHandle::current().metrics().num_workers();
+        index.set_multithread_executor(threads)?;
 
         let parser = QueryParser::for_index(&index, vec![title, body]);
 
         let reader = index.reader()?;
+        let writer = match writer_heap {
+            Some(writer_heap) => {
+                // Each writer thread needs its own share of the heap, so a small heap gets fewer
+                // threads, and a big one gets more, so no share is over Tantivy's limit
+                let max_heap = MAX_WRITER_THREADS * MAX_HEAP_PER_THREAD;
+                if writer_heap > max_heap {
+                    warn!(
+                        "writer heap is over the limit of {} MB, using that instead",
+                        max_heap / 1_000_000
+                    );
+                }
+                let writer_heap = writer_heap.clamp(MIN_HEAP_PER_THREAD, max_heap);
+                let writer_threads = threads
+                    .min(MAX_WRITER_THREADS)
+                    .min(writer_heap / MIN_HEAP_PER_THREAD)
+                    .max(writer_heap.div_ceil(MAX_HEAP_PER_THREAD))
+                    .max(1);
+                Some(index.writer_with_num_threads(writer_threads, writer_heap)?)
+            }
+            None => None,
+        };
 
         Ok(Self {
             schema,
-            index,
             parser,
             reader,
+            writer: Mutex::new(writer),
+            staged: AtomicUsize::new(0),
         })
     }
     pub fn schema(&self) -> Schema {
         self.schema.clone()
     }
-    pub fn writer(&self) -> Result<IndexWriter, Box<dyn Error>> {
-        Ok(self.index.writer(100_000_000)?)
+    /// Add a document for `url`, replacing any existing one, and commit it
+    pub fn upsert(&self, url: &str, doc: TantivyDocument) -> Result<(), Box<dyn Error>> {
+        self.upsert_all(vec![(url.to_string(), doc)])
+    }
+    /// Add documents keyed by URL, replacing any existing ones, and commit them all at once
+    pub fn upsert_all(&self, docs: Vec<(String, TantivyDocument)>) -> Result<(), Box<dyn Error>> {
+        let mut writer = self.writer.lock().unwrap();
+        let writer = writer.as_mut().ok_or(CLOSED)?;
+        self.add(writer, docs)?;
+        writer.commit()?;
+        self.staged.store(0, Ordering::Relaxed);
+
+        Ok(())
+    }
+    /// Add documents keyed by URL, replacing any existing ones, without committing them
+    ///
+    /// They're committed by the next commit, so documents that trickle in (like from crawl
+    /// workers) can be committed in batches. This returns how many documents are waiting to
+    /// be committed.
+    pub fn stage(&self, docs: Vec<(String, TantivyDocument)>) -> Result<usize, Box<dyn Error>> {
+        let mut writer = self.writer.lock().unwrap();
+        let writer = writer.as_mut().ok_or(CLOSED)?;
+        let count = docs.len();
+        self.add(writer, docs)?;
+
+        Ok(self.staged.fetch_add(count, Ordering::Relaxed) + count)
+    }
+    /// Commit any documents that were staged
+    pub fn commit(&self) -> Result<(), Box<dyn Error>> {
+        let mut writer = self.writer.lock().unwrap();
+        if self.staged.load(Ordering::Relaxed) == 0 {
+            return Ok(());
+        }
+        writer.as_mut().ok_or(CLOSED)?.commit()?;
+        self.staged.store(0, Ordering::Relaxed);
+
+        Ok(())
+    }
+    fn add(
+        &self,
+        writer: &mut IndexWriter,
+        docs: Vec<(String, TantivyDocument)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let id = self.schema.get_field("id")?;
+        for (url, mut doc) in docs {
+            doc.add_text(id, &url);
+            writer.delete_term(Term::from_field_text(id, &url));
+            writer.add_document(doc)?;
+        }
+
+        Ok(())
+    }
+    /// Get the document for `url`, if it's indexed
+    pub fn get(&self, url: &str) -> tantivy::Result<Option<TantivyDocument>> {
+        let id = Term::from_field_text(self.schema.get_field("id")?, url);
+
+        let searcher = self.reader.searcher();
+        let top = searcher.search(
+            &TermQuery::new(id, IndexRecordOption::Basic),
+            &TopDocs::with_limit(1),
+        )?;
+        top.first().map(|&(_, addr)| searcher.doc(addr)).transpose()
+    }
+    /// Delete the document for `url` and commit, returning how many were deleted
+    pub fn delete(&self, url: &str) -> Result<usize, Box<dyn Error>> {
+        let id = Term::from_field_text(self.schema.get_field("id")?, url);
+
+        // Make sure anything indexed just now is counted
+        self.reader.reload()?;
+        let count = self.reader.searcher().search(
+            &TermQuery::new(id.clone(), IndexRecordOption::Basic),
+            &Count,
+        )?;
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+        let writer = writer.as_mut().ok_or(CLOSED)?;
+        writer.delete_term(id);
+        writer.commit()?;
+        self.staged.store(0, Ordering::Relaxed);
+
+        Ok(count)
+    }
+    /// Count the documents from each source
+    pub fn source_counts(&self) -> tantivy::Result<BTreeMap<String, usize>> {
+        let field = self.schema.get_field("source")?;
+        let searcher = self.reader.searcher();
+
+        // Collect the sources from every segment's term dictionary
+        let mut sources = BTreeSet::new();
+        for segment in searcher.segment_readers() {
+            let mut terms = segment.inverted_index(field)?.terms().stream()?;
+            while terms.advance() {
+                sources.insert(String::from_utf8_lossy(terms.key()).into_owned());
+            }
+        }
+
+        // Term frequencies include deleted documents, so count matching documents instead
+        sources
+            .into_iter()
+            .map(|source| {
+                let term = Term::from_field_text(field, &source);
+                let query = TermQuery::new(term, IndexRecordOption::Basic);
+                Ok((source, searcher.search(&query, &Count)?))
+            })
+            .collect()
+    }
+    /// Load the metadata of the last commit, which lists the segments in the index
+    pub fn metas(&self) -> tantivy::Result<IndexMeta> {
+        self.reader.searcher().index().load_metas()
     }
     pub fn reader(&self) -> IndexReader {
         self.reader.clone()
@@ -70,4 +280,16 @@ impl SearchIndex {
     pub fn query_parser(&self) -> QueryParser {
         self.parser.clone()
     }
+    /// Commit anything pending and wait for segments to finish merging, so everything is on disk
+    ///
+    /// Nothing can be written to the index after this.
+    pub fn close(&self) -> Result<(), Box<dyn Error>> {
+        let Some(mut writer) = self.writer.lock().unwrap().take() else {
+            return Ok(());
+        };
+        writer.commit()?;
+        writer.wait_merging_threads()?;
+
+        Ok(())
+    }
 }
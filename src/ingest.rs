@@ -0,0 +1,319 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use spider::url::Url;
+use tantivy::TantivyDocument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    crawler,
+    error::AppError,
+    extract::{self, Extracted},
+    title, AppState,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct IndexReq {
+    /// URL of the page to index
+    url: String,
+}
+
+/// A page that was added to the index
+#[derive(Serialize, ToSchema)]
+pub struct IndexedPage {
+    url: String,
+    title: String,
+    /// Source the page was filed under, for stats and boosts
+    source: String,
+}
+
+/// Parse a URL given to the API, which has to be `http` or `https`
+fn parse_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|err| format!("invalid URL: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https URLs can be indexed".to_string());
+    }
+
+    Ok(url)
+}
+
+/// `POST /api/index`: fetch and index a single page right away
+///
+/// If the page is already indexed, it's replaced.
+#[utoipa::path(
+    post,
+    path = "/api/index",
+    request_body = IndexReq,
+    responses(
+        (status = 200, description = "The page was indexed", body = IndexedPage),
+        (status = 400, description = "The URL is invalid"),
+        (status = 401, description = "No admin key was given"),
+        (status = 403, description = "The URL is blocked by the config"),
+        (status = 502, description = "The page couldn't be fetched"),
+    ),
+)]
+pub async fn index_url(
+    State(st): State<AppState>,
+    Json(req): Json<IndexReq>,
+) -> Result<Json<IndexedPage>, AppError> {
+    let url = parse_url(&req.url).map_err(AppError::bad_request)?;
+    let config = st.config();
+    if !config.filter.allows(&url) {
+        return Err(AppError::new(StatusCode::FORBIDDEN, "that URL is blocked"));
+    }
+
+    let page = crawler::fetch(&url, &config.crawler)
+        .await
+        .ok_or_else(|| AppError::new(StatusCode::BAD_GATEWAY, "couldn't fetch the page"))?;
+
+    let source = crawler::source_for(&config.targets, &url);
+    if !page.ok() {
+        st.crawldb.failed(url.as_str(), &source, &page.error())?;
+        return Err(AppError::new(
+            StatusCode::BAD_GATEWAY,
+            format!("couldn't fetch the page: {}", page.error()),
+        ));
+    }
+    st.crawldb.fetched(&page, &source)?;
+
+    let (title, links) = crawler::index_page(
+        &url,
+        &page.html,
+        &source,
+        &config,
+        &st.extractors,
+        &st.se,
+        &st.index,
+    )
+    .await
+    .map_err(|err| err.to_string())?;
+    st.crawldb.set_links(url.as_str(), &links)?;
+    info!("indexed {url} on request");
+
+    Ok(Json(IndexedPage {
+        url: url.to_string(),
+        title,
+        source,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DocumentParams {
+    /// URL of the document
+    url: String,
+}
+
+/// `DELETE /api/document`: remove a document from the index
+#[utoipa::path(
+    delete,
+    path = "/api/document",
+    params(DocumentParams),
+    responses(
+        (status = 204, description = "The document was deleted"),
+        (status = 401, description = "No admin key was given"),
+        (status = 404, description = "No document has that URL"),
+    ),
+)]
+pub async fn delete_document(
+    State(st): State<AppState>,
+    Query(params): Query<DocumentParams>,
+) -> Result<StatusCode, AppError> {
+    // Documents are stored under their normalized URL
+    let url = Url::parse(&params.url)
+        .map(|url| url.to_string())
+        .unwrap_or(params.url);
+
+    let deleted = st.index.delete(&url).map_err(|err| err.to_string())?;
+    if deleted == 0 {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "no document has that URL",
+        ));
+    }
+    st.crawldb.remove(&url)?;
+    info!("deleted {url} from the index");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Most documents accepted in a single request
+const MAX_DOCUMENTS: usize = 1000;
+
+/// A document pushed to the index by an external pipeline (or a crawl worker)
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DocumentReq {
+    pub url: String,
+    pub title: String,
+    /// Plain text body
+    pub body: String,
+    /// Source to file the document under (the URL's host by default)
+    pub source: Option<String>,
+    /// ISO 639-1 language code (detected from the body by default)
+    pub lang: Option<String>,
+    /// Short description, shown when no snippet can be made from the body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// What kind of page this is, like `article` (from its `og:type`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_type: Option<String>,
+    /// Absolute URL of the page's preview image (from its `og:image`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Embedding of the title, if it was already generated with the same model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    /// Absolute URLs of the pages this one links to, for ranking by authority
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentsRes {
+    /// Number of documents indexed
+    indexed: usize,
+    /// Number of documents left out because their URL is blocked by the config
+    blocked: usize,
+}
+
+/// `POST /api/documents`: embed and index documents from outside the crawler
+///
+/// Documents that are already indexed (by URL) are replaced.
+#[utoipa::path(
+    post,
+    path = "/api/documents",
+    request_body = Vec<DocumentReq>,
+    responses(
+        (status = 200, description = "The documents were indexed", body = DocumentsRes),
+        (status = 400, description = "A URL is invalid, or there are too many documents"),
+        (status = 401, description = "No admin key was given"),
+    ),
+)]
+pub async fn index_documents(
+    State(st): State<AppState>,
+    Json(docs): Json<Vec<DocumentReq>>,
+) -> Result<Json<DocumentsRes>, AppError> {
+    let pushed = docs.len();
+    let indexed = documents(&st, docs).await?;
+
+    let count = indexed.len();
+    st.index
+        .upsert_all(indexed)
+        .map_err(|err| err.to_string())?;
+    info!("indexed {count} documents pushed through the API");
+
+    Ok(Json(DocumentsRes {
+        indexed: count,
+        blocked: pushed - count,
+    }))
+}
+
+/// Check, embed, and build the documents for pushed documents, leaving out blocked ones
+///
+/// This records their links, but doesn't index them.
+pub async fn documents(
+    st: &AppState,
+    docs: Vec<DocumentReq>,
+) -> Result<Vec<(String, TantivyDocument)>, AppError> {
+    if docs.len() > MAX_DOCUMENTS {
+        return Err(AppError::bad_request(format!(
+            "too many documents (at most {MAX_DOCUMENTS} per request)"
+        )));
+    }
+
+    let dim = st.se.dim();
+    let mut urls = Vec::with_capacity(docs.len());
+    for (i, doc) in docs.iter().enumerate() {
+        let url = parse_url(&doc.url)
+            .map_err(|err| AppError::bad_request(format!("document {i}: {err}")))?;
+        if doc.embedding.as_ref().is_some_and(|e| e.len() != dim) {
+            return Err(AppError::bad_request(format!(
+                "document {i}'s embedding doesn't have {dim} dimensions"
+            )));
+        }
+        urls.push(url);
+    }
+
+    let schema = st.index.schema();
+    let mut indexed = Vec::with_capacity(docs.len());
+    // Blocked documents are left out, the same as blocked pages are when crawling
+    let config = st.config();
+    let mut docs = docs
+        .into_iter()
+        .zip(urls)
+        .filter(|(_, url)| config.filter.allows(url))
+        .map(|(mut doc, url)| {
+            let source = doc
+                .source
+                .take()
+                .unwrap_or_else(|| crawler::source_for(&config.targets, &url));
+            let title = title::clean(&config.title_rules, &source, &doc.title);
+            // An embedding of the original title doesn't match the cleaned one
+            if title != doc.title {
+                doc.title = title;
+                doc.embedding = None;
+            }
+            doc.source = Some(source);
+            (doc, url)
+        });
+    // Embed a batch at a time, to keep inference memory in check
+    let batch_size = config.memory.embedding_batch.max(1);
+    loop {
+        let batch: Vec<_> = docs.by_ref().take(batch_size).collect();
+        if batch.is_empty() {
+            break;
+        }
+
+        // Only embed the documents that didn't come with an embedding
+        let titles: Vec<_> = batch
+            .iter()
+            .filter(|(doc, _)| doc.embedding.is_none())
+            .map(|(doc, _)| doc.title.clone())
+            .collect();
+        let mut embeddings = if titles.is_empty() {
+            Vec::new()
+        } else {
+            st.se
+                .acquire()
+                .await
+                .generate_embeddings(titles)
+                .map_err(|err| err.to_string())?
+        }
+        .into_iter();
+
+        for (doc, url) in batch {
+            let embedding = match doc.embedding {
+                Some(embedding) => embedding,
+                None => embeddings.next().ok_or("missing an embedding")?,
+            };
+            let source = doc.source.unwrap_or_default();
+            let page = Extracted {
+                lang: doc
+                    .lang
+                    .map(|lang| lang.to_ascii_lowercase())
+                    .unwrap_or_else(|| extract::guess_lang(&doc.body)),
+                title: doc.title,
+                body: doc.body,
+                metadata: [
+                    ("description", doc.description),
+                    ("og:type", doc.page_type),
+                    ("og:image", doc.image),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value?)))
+                .collect(),
+                links: doc.links,
+                ..Default::default()
+            };
+            st.crawldb.set_links(url.as_str(), &page.links)?;
+
+            let doc = crawler::document(&schema, &url, page, &source, &embedding)
+                .map_err(|err| err.to_string())?;
+            indexed.push((url.to_string(), doc));
+        }
+    }
+
+    Ok(indexed)
+}
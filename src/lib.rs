@@ -0,0 +1,527 @@
+//! Mini Search: semantic search for programming documentation
+//!
+//! An [Engine] ties together the search index, the embedding model, and the crawler. It can
+//! serve the search page and HTTP API by itself, or its [router](Engine::router) can be merged
+//! into another axum app:
+//!
+//! ```no_run
+//! use std::net::SocketAddr;
+//!
+//! use axum::{routing::get, Router};
+//! use mini_search::Engine;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let engine = Engine::builder().build().await?;
+//!
+//! let app = Router::new()
+//!     .route("/hello", get(|| async { "hello" }))
+//!     .merge(engine.router());
+//!
+//! // Rate limiting needs to know the address of each client
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+//! axum::serve(
+//!     listener,
+//!     app.into_make_service_with_connect_info::<SocketAddr>(),
+//! )
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    error::Error,
+    sync::{Arc, RwLock},
+};
+
+use admin::CrawlJobs;
+use authority::Authority;
+use clicks::Clicks;
+use crawldb::CrawlDb;
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap},
+    middleware::from_fn_with_state,
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use error::AppError;
+use extract::Extractors;
+use history::History;
+use i18n::Locales;
+use queue::WorkQueue;
+use quota::Usage;
+use ratelimit::RateLimiter;
+use saved::SavedPages;
+use search::DEFAULT_LANG;
+use tantivy::{query::QueryParser, schema::Schema, IndexReader};
+use tera::{Context, Tera};
+use tokio_util::sync::CancellationToken;
+use tower_http::{
+    compression::CompressionLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+use utoipa::{IntoParams, ToSchema};
+
+#[macro_use]
+extern crate tracing;
+extern crate axum;
+extern crate axum_server;
+extern crate tokio;
+#[macro_use]
+extern crate serde;
+extern crate candle_core;
+extern crate candle_nn;
+extern crate candle_transformers;
+extern crate csv;
+extern crate fend_core;
+extern crate hyper_util;
+extern crate isolang;
+extern crate regex;
+extern crate rusqlite;
+extern crate rust_embed;
+extern crate sd_notify;
+extern crate spider;
+extern crate tantivy;
+extern crate tera;
+extern crate time;
+extern crate tokenizers;
+extern crate tokio_util;
+extern crate toml;
+extern crate tower_http;
+extern crate utoipa;
+extern crate whatlang;
+
+mod admin;
+mod api;
+mod auth;
+mod authority;
+mod bench;
+mod breadcrumb;
+mod browser;
+mod calc;
+mod clicks;
+mod config;
+mod crawldb;
+mod crawler;
+mod devdocs;
+mod docset;
+mod engine;
+mod error;
+mod extract;
+mod favicon;
+mod feed;
+mod filter;
+mod fixtures;
+mod health;
+mod history;
+mod i18n;
+mod index;
+mod ingest;
+mod preview;
+mod query;
+mod queue;
+mod quota;
+mod ratelimit;
+mod related;
+mod replica;
+mod sanitize;
+mod saved;
+mod search;
+mod server;
+mod store;
+mod suggest;
+mod systemd;
+mod templates;
+mod title;
+mod transformers;
+mod worker;
+
+pub use auth::{ApiKey, AuthConfig};
+pub use authority::AuthorityConfig;
+pub use bench::{bench, BenchReport};
+pub use clicks::ClickConfig;
+pub use config::{Boost, Config, LogFormat, MemoryConfig};
+pub use crawldb::{CrawlDb, PageMeta, SourceMeta};
+pub use crawler::{crawl_targets, CrawlStats, CrawlTarget, CrawlerConfig, ScrapedPage};
+pub use devdocs::DevDocsSource;
+pub use docset::export_docset;
+pub use engine::{Engine, EngineBuilder};
+pub use extract::{Extracted, Extractor, Extractors, SelectorExtractor};
+pub use filter::UrlFilter;
+pub use fixtures::{FixtureConfig, FixtureMode};
+pub use history::HistoryConfig;
+pub use index::SearchIndex;
+pub use query::QueryConfig;
+pub use ratelimit::RateLimitConfig;
+pub use replica::ReplicaConfig;
+pub use search::{Answer, Explain, Res, SearchOptions, SearchRes, Timings};
+pub use server::{on_reload_signal, shutdown_signal, TlsConfig};
+pub use title::TitleRule;
+pub use transformers::{Embedder, EmbedderGuard, SentEmbed};
+pub use worker::run_worker;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchParams {
+    /// Search query
+    #[serde(rename = "q")]
+    query: Option<String>,
+    /// Include a ranking explanation for each result
+    #[serde(default, deserialize_with = "flag")]
+    explain: bool,
+    /// Include how long each stage of the search took
+    #[serde(default, deserialize_with = "flag")]
+    debug: bool,
+    /// Language to limit results to (`all` for every language)
+    lang: Option<String>,
+    /// Only include pages crawled on or after a day (like `2024-06-30`), or in the past day,
+    /// week, month, or year (`past_day`, `past_week`, `past_month`, or `past_year`)
+    after: Option<String>,
+    /// Only include pages crawled before a day (like `2024-06-30`)
+    before: Option<String>,
+    /// Download the results as a file instead of showing them
+    #[serde(default)]
+    format: Format,
+}
+
+/// Format the search page responds with
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Html,
+    Json,
+    Csv,
+}
+
+impl SearchParams {
+    fn options(&self) -> SearchOptions {
+        SearchOptions {
+            explain: self.explain,
+            debug: self.debug,
+            lang: self
+                .lang
+                .clone()
+                .unwrap_or_else(|| DEFAULT_LANG.to_string())
+                .to_ascii_lowercase(),
+            // Empty dates come from the search form's "any time"
+            after: self.after.clone().filter(|date| !date.is_empty()),
+            before: self.before.clone().filter(|date| !date.is_empty()),
+        }
+    }
+}
+
+/// Deserialize a query string flag like `explain=1` or `explain=true`
+fn flag<'de, D: serde::Deserializer<'de>>(de: D) -> Result<bool, D::Error> {
+    let s = String::deserialize(de)?;
+    Ok(matches!(s.as_str(), "1" | "true" | "on" | "yes"))
+}
+
+async fn search_page(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, AppError> {
+    let templates = st.page_templates(&headers);
+
+    // If the query param was set, we'll perform a search.
+    // Otherwise, we just show the search box.
+    let opts = params.options();
+    let Some(q) = params.query else {
+        let mut ctx = Context::new();
+        ctx.insert("history", &st.config().history.enabled);
+        return Ok(Html(templates.render("index.html", &ctx)?).into_response());
+    };
+
+    let res = search::search(&st, q, opts).await?;
+
+    Ok(match params.format {
+        Format::Html => {
+            history::record(&st, &headers, &res.query, &res.lang);
+            let browser = browser::id(&headers);
+
+            let timings = res.timings;
+            let mut ctx = Context::from_serialize(res)?;
+            ctx.insert("timings", &timings);
+            ctx.insert("track_clicks", &st.config().clicks.enabled);
+            ctx.insert("saved", &saved::urls(&st, browser.as_deref()));
+
+            let mut res = Html(templates.render("index.html", &ctx)?).into_response();
+            // Give the browser an ID, for saving pages and keeping its history
+            if browser.is_none() {
+                res.headers_mut()
+                    .insert(header::SET_COOKIE, browser::new_cookie()?);
+            }
+            res
+        }
+        Format::Json => (
+            [(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"search-results.json\"",
+            )],
+            Json(res),
+        )
+            .into_response(),
+        Format::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"search-results.csv\"",
+                ),
+            ],
+            res.to_csv()?,
+        )
+            .into_response(),
+    })
+}
+
+/// Get the URL this instance is reachable at, for links that leave the page
+fn base_url(config: &Config, headers: &HeaderMap) -> String {
+    if let Some(url) = &config.public_url {
+        return url.trim_end_matches('/').to_string();
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost:8080");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .unwrap_or("http");
+
+    format!("{scheme}://{host}")
+}
+
+/// OpenSearch description, so browsers can add Mini Search as a search engine
+async fn opensearch(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let templates = st.templates();
+
+    let mut ctx = Context::new();
+    ctx.insert("base_url", &base_url(&st.config(), &headers));
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "application/opensearchdescription+xml",
+        )],
+        templates.render("opensearch.xml", &ctx)?,
+    ))
+}
+
+async fn stats_page(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Html<String>, AppError> {
+    let templates = st.page_templates(&headers);
+
+    // Count what's in the index now, so this is right after background crawls and restarts
+    let sources = st.index.source_counts()?;
+
+    let crawled = st.crawldb.sources()?;
+
+    #[derive(Serialize)]
+    struct Row<'a> {
+        source: &'a str,
+        pages: usize,
+        /// Pages whose last fetch failed
+        failing: usize,
+        /// When a page was last crawled
+        last_crawled: Option<String>,
+    }
+    let rows: Vec<_> = sources
+        .iter()
+        .map(|(source, &pages)| {
+            let crawled = crawled.get(source).cloned().unwrap_or_default();
+            Row {
+                source,
+                pages,
+                failing: crawled.failing,
+                last_crawled: crawled
+                    .last_fetched
+                    .map(|secs| feed::rfc3339(tantivy::DateTime::from_timestamp_secs(secs))),
+            }
+        })
+        .collect();
+
+    let mut ctx = Context::new();
+    ctx.insert("total", &sources.values().sum::<usize>());
+    ctx.insert("sources", &rows);
+
+    Ok(Html(templates.render("stats.html", &ctx)?))
+}
+
+/// Span that a request's log lines are attached to
+fn request_span(req: &Request) -> tracing::Span {
+    let id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+
+    info_span!("request", id, method = %req.method(), uri = %req.uri())
+}
+
+#[derive(Clone)]
+struct AppState {
+    index: Arc<SearchIndex>,
+    /// What's known about fetching each crawled URL
+    crawldb: Arc<CrawlDb>,
+    reader: IndexReader,
+    parser: QueryParser,
+    schema: Schema,
+    se: Arc<Embedder>,
+    extractors: Arc<Extractors>,
+    templates: Tera,
+    /// Messages for the UI in each language
+    locales: Arc<Locales>,
+    /// Swapped out when the config is reloaded
+    config: Arc<RwLock<Arc<Config>>>,
+    jobs: CrawlJobs,
+    clicks: Arc<Clicks>,
+    /// Authority of each page, from the link graph
+    authority: Arc<Authority>,
+    saved: Arc<SavedPages>,
+    history: Arc<History>,
+    /// Targets waiting for crawl workers
+    queue: WorkQueue,
+    limiter: Arc<RateLimiter>,
+    /// API requests made with each key
+    usage: Arc<Usage>,
+    /// Triggered when the server is shutting down
+    shutdown: CancellationToken,
+}
+impl AppState {
+    /// Get the current config
+    fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Reload the config file
+    ///
+    /// Changes to crawl targets, boosts, auth, and rate limits take effect right away, but the
+    /// listen address and TLS settings are only read on startup.
+    fn reload_config(&self) -> Result<(), Box<dyn Error>> {
+        let config = Config::load()?;
+        *self.config.write().unwrap() = Arc::new(config);
+        info!("reloaded config");
+
+        Ok(())
+    }
+
+    /// Get the HTML templates
+    fn templates(&self) -> Tera {
+        let mut templates = self.templates.clone();
+
+        // Reload the HTML templates for dev profile (unoptimized build)
+        // This only works when they were loaded from disk, which is fine to ignore
+        #[cfg(debug_assertions)]
+        let _ = templates.full_reload();
+
+        templates
+    }
+
+    /// Get the HTML templates, with the UI in the language the request asks for
+    fn page_templates(&self, headers: &HeaderMap) -> Tera {
+        let mut templates = self.templates();
+        i18n::localize(
+            &mut templates,
+            &self.locales,
+            self.locales.negotiate(headers),
+        );
+        templates
+    }
+}
+
+/// Build the router for the search page and HTTP API
+fn router(st: AppState) -> Router {
+    let api = Router::new()
+        .route("/api/search", get(api::search))
+        .route_layer(from_fn_with_state(st.clone(), ratelimit::limit))
+        // Batches are charged for each query they have, rather than by the middleware
+        .route("/api/search/batch", post(api::search_batch))
+        .route("/api/suggest", get(api::suggest))
+        .route("/api/openapi.json", get(api::openapi))
+        .route_layer(from_fn_with_state(st.clone(), auth::require_api_key));
+
+    let mut admin = Router::new()
+        .route("/admin/reload", post(admin::reload_config))
+        .route("/admin/page", get(admin::page_meta))
+        .route("/admin/usage", get(quota::usage))
+        .route("/admin/replica/manifest", get(replica::get_manifest))
+        .route("/admin/replica/files/:name", get(replica::get_file));
+    // Replicas only get their index from the primary, so anything that writes to it is left out
+    if st.config().replica.is_none() {
+        admin = admin
+            .route("/admin/crawl", post(admin::start_crawl))
+            .route("/admin/crawl/:id", get(admin::crawl_status))
+            .route(
+                "/admin/queue",
+                get(queue::queue_status).post(queue::enqueue),
+            )
+            .route("/admin/queue/lease", post(queue::lease))
+            .route("/admin/queue/:id/pages", post(queue::pages))
+            .route("/admin/queue/:id/done", post(queue::finish))
+            .route("/api/index", post(ingest::index_url))
+            .route("/api/documents", post(ingest::index_documents))
+            .route("/api/document", delete(ingest::delete_document));
+    }
+    let admin = admin.route_layer(from_fn_with_state(st.clone(), auth::require_admin));
+
+    // Serve static assets from disk if they're there, so they can be customized
+    let assets = if templates::assets_on_disk() {
+        Router::new().nest_service("/static", ServeDir::new("static"))
+    } else {
+        Router::new().route("/static/*path", get(templates::asset))
+    };
+
+    Router::new()
+        .route(
+            "/",
+            get(search_page).route_layer(from_fn_with_state(st.clone(), ratelimit::limit)),
+        )
+        .route(
+            "/preview",
+            get(preview::preview).route_layer(from_fn_with_state(st.clone(), ratelimit::limit)),
+        )
+        .route(
+            "/click",
+            get(clicks::click).route_layer(from_fn_with_state(st.clone(), ratelimit::limit)),
+        )
+        .route("/saved", get(saved::saved_page).post(saved::save))
+        .route("/saved/remove", post(saved::remove))
+        .route("/history", get(history::history_page))
+        .route("/history/clear", post(history::clear))
+        .route("/stats", get(stats_page))
+        .route("/locale", post(i18n::set_locale))
+        .route("/opensearch.xml", get(opensearch))
+        .route("/feed.xml", get(feed::feed))
+        .route("/favicon/:domain", get(favicon::favicon))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .merge(assets)
+        .merge(api)
+        .merge(admin)
+        .fallback(error::not_found)
+        .layer(from_fn_with_state(st.clone(), error::render_html))
+        .layer(CompressionLayer::new())
+        // Tag every request with an ID, which is attached to its log lines and echoed back in
+        // the `X-Request-Id` header
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(st)
+}
@@ -1,374 +1,164 @@
-use std::{
-    error::Error,
-    sync::Arc,
-    time::{Duration, Instant},
-};
+use std::{env, error::Error, fs, path::PathBuf};
 
-use axum::{
-    extract::{Query, State},
-    response::{Html, IntoResponse},
-    routing::get,
-    Router,
-};
-use crawler::crawl;
-use index::SearchIndex;
-use tantivy::{
-    collector::TopDocs,
-    query::QueryParser,
-    schema::{Schema, Value},
-    IndexReader, SnippetGenerator, TantivyDocument,
-};
-use tera::{Context, Tera};
-use tokio::{net::TcpListener, sync::Mutex};
-use transformers::SentEmbed;
+use clap::{Parser, Subcommand};
+use mini_search::{Config, Engine, Extractors, LogFormat, SearchIndex, SearchOptions};
+use spider::url::Url;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
 
 #[macro_use]
-extern crate log;
-extern crate axum;
-extern crate tokio;
-#[macro_use]
-extern crate serde;
-extern crate candle_core;
-extern crate candle_nn;
-extern crate candle_transformers;
-extern crate env_logger;
+extern crate tracing;
+extern crate clap;
+extern crate mini_search;
 extern crate spider;
-extern crate tantivy;
-extern crate tera;
-extern crate tokenizers;
-
-mod crawler;
-mod index;
-mod transformers;
-
-#[derive(Deserialize)]
-struct SearchParams {
-    #[serde(rename(deserialize = "q"))]
-    query: Option<String>,
-}
-
-#[derive(Serialize, Clone)]
-struct Res {
-    url: String,
-    title: String,
-    snippet: String,
-}
-#[derive(Serialize)]
-struct SearchRes {
-    query: String,
-    results: Vec<Res>,
-    time: String,
+extern crate tokio;
+extern crate tokio_util;
+extern crate tracing_subscriber;
+
+/// Semantic search engine for programming documentation
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Don't crawl on startup, even if the index is empty
+    #[arg(long)]
+    no_crawl: bool,
+    /// Run as a crawl worker for the instance at this URL, instead of serving
+    ///
+    /// The worker authenticates with the admin key in `MINI_SEARCH_WORKER_KEY`.
+    #[arg(long, value_name = "URL")]
+    worker: Option<Url>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Deserialize, Serialize)]
-struct MiniDoc {
-    url: String,
-    title: String,
-    body: String,
-    embedding: Vec<u8>,
+#[derive(Subcommand)]
+enum Command {
+    /// Export the index as a Dash docset, for offline doc browsers like Dash and Zeal
+    ExportDocset {
+        /// Docset to create
+        #[arg(default_value = "mini-search.docset")]
+        path: PathBuf,
+        /// Name to show in the doc browser
+        #[arg(long, default_value = "Mini Search")]
+        name: String,
+    },
+    /// Run the queries in a file (one per line) against the index and report their latencies
+    Bench {
+        queries: PathBuf,
+        /// Times to run each query
+        #[arg(long, default_value_t = 10)]
+        runs: usize,
+        /// Language to limit results to (`all` for every language)
+        #[arg(long, default_value = "en")]
+        lang: String,
+    },
 }
 
-async fn search(
-    State(st): State<AppState>,
-    Query(params): Query<SearchParams>,
-) -> impl IntoResponse {
-    // If the query param was set, we'll perform a search.
-    // Otherwise, we just show the search box.
-    if let Some(q) = params.query {
-        let AppState {
-            reader,
-            parser,
-            schema,
-            templates,
-            se,
-            ..
-        } = st;
-
-        // Reload the HTML templates for dev profile (unoptimized build)
-        let mut templates = templates.clone();
-        #[cfg(debug_assertions)]
-        templates.full_reload().unwrap();
-
-        let mut snippet_gen_tm = Duration::default();
-
-        let total_st = Instant::now();
-
-        // Spawn a future to generate an embedding for the search query
-        // and keep the join handle for later
-        let jh = {
-            let se = se.clone();
-            let query = q.clone();
-            tokio::spawn(async move {
-                let st = Instant::now();
-                (se.lock().await.generate_embedding(query).ok(), st.elapsed())
-            })
-        };
-
-        let searcher = reader.searcher();
-
-        let parse_st = Instant::now();
-        let query = parser.parse_query(&q).expect("failed to parse query");
-        let parse_tm = parse_st.elapsed();
-
-        let search_st = Instant::now();
-        let results_raw = searcher
-            .search(&query, &TopDocs::with_limit(20))
-            .expect("search failed");
-        let search_tm = search_st.elapsed();
-
-        let mut results = Vec::new();
-
-        // Fetch documents from the search index and extract their embeddings
-        let fetch_st = Instant::now();
-        let docs_with_embeddings: Vec<(Vec<f32>, TantivyDocument)> = results_raw
-            .iter()
-            .map(|&(_, doc_addr)| {
-                let doc = searcher
-                    .doc::<TantivyDocument>(doc_addr)
-                    .expect("couldn't get doc");
-                let embedding = doc
-                    .get_first(schema.get_field("embedding").unwrap())
-                    .unwrap()
-                    .as_bytes()
-                    .unwrap();
-                // Convert the Vec<u8> storage back to Vec<f32>
-                // This is safe, as long as the input size is a multiple of 4 bytes
-                let embedding = unsafe {
-                    std::slice::from_raw_parts(
-                        embedding.as_ptr() as *const f32,
-                        embedding.len() / 4,
-                    )
-                    .to_vec()
-                };
-
-                (embedding.clone(), doc)
-            })
-            .collect();
-        let fetch_tm = fetch_st.elapsed();
-
-        // Wait for the future to generate an embedding
-        let (embedding, embedding_gen_tm) = jh.await.expect("something broke");
-
-        // Sort by cosine similarity
-        let sort_st = Instant::now();
-        let scores = se
-            .lock()
-            .await
-            .sort_by_similarity(
-                embedding.unwrap(),
-                docs_with_embeddings.iter().map(|x| x.0.clone()),
-            )
-            .unwrap();
-        let sort_tm = sort_st.elapsed();
-
-        // Create a snippet generator
-        let mut snippet_gen_st = Instant::now();
-        let snippet_gen =
-            SnippetGenerator::create(&searcher, &query, schema.get_field("body").unwrap()).unwrap();
-        snippet_gen_tm += snippet_gen_st.elapsed();
-
-        // Get fields we need for the top 10 results and generate a snippet relevant to the search
-        // query for each
-        for &(i, _score) in scores.iter().take(10) {
-            let doc = docs_with_embeddings.get(i).unwrap().1.clone();
-
-            let url = doc
-                .get_first(schema.get_field("url").unwrap())
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .to_string();
-            let title = doc
-                .get_first(schema.get_field("title").unwrap())
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .to_string();
-
-            // Generate snippet for the document
-            snippet_gen_st = Instant::now();
-            let snippet = snippet_gen.snippet_from_doc(&doc).to_html();
-            snippet_gen_tm += snippet_gen_st.elapsed();
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    // Load the config first, since it decides how to log
+    let config = Config::load()?;
+
+    // Log levels are set with `RUST_LOG`, like `RUST_LOG=info`
+    let filter = EnvFilter::from_default_env();
+    match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(filter)
+            .init(),
+    }
 
-            results.push(Res {
-                url,
-                title,
-                snippet,
-            });
+    match args.command {
+        Some(Command::ExportDocset { path, name }) => {
+            // Read-only, so this works while the server is running
+            let index = SearchIndex::read_only().await?;
+            let count = mini_search::export_docset(&index, &path, &name)?;
+            println!("exported {count} pages to {}", path.display());
+            return Ok(());
         }
-
-        let total_tm = total_st.elapsed();
-
-        Html(templates.render("index.html", &Context::from_serialize(SearchRes {
-            query: q,
-            results,
-            time: format!(
-                "{total_tm:?} = parse({parse_tm:?}) + search({search_tm:?}) + fetch({fetch_tm:?}) + embedding({embedding_gen_tm:?}) + sort({sort_tm:?})",
-            ),
-        }).unwrap()).unwrap()).into_response()
-    } else {
-        // Reload the HTML templates for dev profile (unoptimized build)
-        let mut templates = st.templates.clone();
-        #[cfg(debug_assertions)]
-        templates.full_reload().unwrap();
-
-        Html(templates.render("index.html", &Context::default()).unwrap()).into_response()
+        Some(Command::Bench {
+            queries,
+            runs,
+            lang,
+        }) => {
+            // Blank lines and `#` comments are skipped
+            let queries: Vec<String> = fs::read_to_string(&queries)?
+                .lines()
+                .map(str::trim)
+                .filter(|q| !q.is_empty() && !q.starts_with('#'))
+                .map(str::to_string)
+                .collect();
+
+            // Read-only, so this works while the server is running
+            let engine = Engine::builder().config(config).read_only().build().await?;
+            let opts = SearchOptions {
+                explain: false,
+                lang,
+                debug: true,
+                after: None,
+                before: None,
+            };
+            let report = mini_search::bench(&engine, &queries, runs, opts)
+                .await
+                .map_err(|err| err.to_string())?;
+            print!("{report}");
+            return Ok(());
+        }
+        None => {}
     }
-}
-
-async fn stats_page(State(st): State<AppState>) -> impl IntoResponse {
-    let AppState {
-        templates, stats, ..
-    } = st;
-
-    // Reload the HTML templates for dev profile (unoptimized build)
-    let mut templates = templates.clone();
-    #[cfg(debug_assertions)]
-    templates.full_reload().unwrap();
-
-    Html(
-        templates
-            .render("stats.html", &Context::from_serialize(stats).unwrap())
-            .unwrap(),
-    )
-}
 
-#[derive(Clone)]
-struct AppState {
-    reader: IndexReader,
-    parser: QueryParser,
-    schema: Schema,
-    se: Arc<Mutex<SentEmbed>>,
-    templates: Tera,
-    stats: CrawlStats,
-}
-
-#[derive(Serialize, Clone, Copy)]
-struct CrawlStats {
-    python_ct: usize,
-    ruby_ct: usize,
-    rust_std_ct: usize,
-    docs_rs_ct: usize,
-}
-
-async fn run_crawl(se: &mut SentEmbed, index: &SearchIndex) -> Result<CrawlStats, Box<dyn Error>> {
-    // Crawl only Python 2.7, 3.8, 3.12, and 2.7
-    let python_ct = crawl(
-        "https://docs.python.org/3.13/",
-        |url| {
-            let path = url.path();
-            path.starts_with("/3.13")
-                || path.starts_with("/3.12")
-                || path.starts_with("/3.8")
-                || path.starts_with("/2.7")
-        },
-        se,
-        &index,
-    )
-    .await?;
-
-    let ruby_ct = crawl(
-        "https://docs.ruby-lang.org/",
-        |url| {
-            let path = url.path();
-            (path.starts_with("/en/3.3")
-                || path.starts_with("/en/3.4")
-                || path.starts_with("/en/master"))
-                && !(path.ends_with("/index.html") || path.ends_with("/"))
-        },
-        se,
-        &index,
-    )
-    .await?;
-
-    let rust_std_ct = crawl(
-        "https://doc.rust-lang.org/stable/std/index.html",
-        |url| {
-            let path = url.path();
-            path.starts_with("/stable")
-                && !path.ends_with("/index.html")
-                && !path.ends_with("/all.html")
-        },
-        se,
-        &index,
-    )
-    .await?;
+    // Triggered by ctrl+c (or SIGTERM), to stop crawling and drain connections
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            mini_search::shutdown_signal().await;
+            info!("shutting down");
+            shutdown.cancel();
+        }
+    });
 
-    let mut docs_rs_ct = 0usize;
-    for (name, version) in [
-        ("log", "0.4.22"),
-        ("tokio", "1.41.0"),
-        ("serde", "1.0.214"),
-        ("axum", "0.7.7"),
-        ("candle-core", "0.7.2"),
-        ("candle-nn", "0.7.2"),
-        ("candle-transformers", "0.7.2"),
-        ("spider", "2.11.20"),
-        ("tantivy", "0.22.0"),
-        ("tera", "1.20.0"),
-        ("tokenizers", "0.20.1"),
-        ("owo_colors", "4.1.0"),
-        ("fend_core", "1.5.3"),
-        ("pnet", "0.35.0"),
-    ] {
-        let base_path = format!("/{name}/{version}/{name}");
-        docs_rs_ct += crawl(
-            &format!("https://docs.rs{base_path}/index.html"),
-            |url| {
-                let path = url.path();
-                path.starts_with(&base_path)
-                    && !path.ends_with("/index.html")
-                    && !path.ends_with("/all.html")
-            },
-            se,
-            &index,
-        )
-        .await?;
+    if let Some(primary) = args.worker {
+        let key = env::var("MINI_SEARCH_WORKER_KEY")
+            .map_err(|_| "MINI_SEARCH_WORKER_KEY must be set to one of the primary's admin keys")?;
+        return mini_search::run_worker(primary, key, Extractors::default(), config, shutdown)
+            .await;
     }
 
-    Ok(CrawlStats {
-        python_ct,
-        ruby_ct,
-        rust_std_ct,
-        docs_rs_ct,
-    })
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-
-    let tera = Tera::new("views/*.html").unwrap();
-
-    let mut se = SentEmbed::new()?;
-
-    let index = SearchIndex::new().await.unwrap();
-
-    let stats = run_crawl(&mut se, &index).await?;
-
-    let r = Router::new()
-        .route("/", get(search))
-        .route("/stats", get(stats_page))
-        .with_state(AppState {
-            reader: index.reader(),
-            parser: index.query_parser(),
-            schema: index.schema(),
-            se: Arc::new(Mutex::new(se)),
-            templates: tera,
-            stats,
-        });
-
-    let srv = axum::serve(
-        TcpListener::bind("0.0.0.0:8080").await?,
-        r.into_make_service(),
-    );
+    let engine = Engine::builder()
+        .config(config)
+        .shutdown(shutdown)
+        .build()
+        .await?;
 
-    // Run the web server until a fatal error is encountered
-    // or ctrl+c is pressed
-    tokio::select! {
-        _ = srv => {}
-        _ = tokio::signal::ctrl_c() => {}
+    tokio::spawn({
+        let engine = engine.clone();
+        async move {
+            mini_search::on_reload_signal(|| {
+                if let Err(err) = engine.reload_config() {
+                    error!("couldn't reload config: {err}");
+                }
+            })
+            .await;
+        }
+    });
+
+    // Crawl in the background, so searches are served from the existing index in the meantime
+    if args.no_crawl {
+        info!("not crawling, since --no-crawl was given");
+    } else if engine.is_replica() {
+        info!("not crawling, since this is a replica");
+    } else if engine.needs_crawl() {
+        engine.crawl(None);
+    } else {
+        info!(
+            "the index already has {} documents, so not crawling",
+            engine.num_docs()
+        );
     }
 
-    Ok(())
+    engine.serve().await
 }
@@ -0,0 +1,74 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use tantivy::schema::Value;
+use tera::escape_html;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{error::AppError, sanitize, search, AppState};
+
+/// Characters of a page's body included in its preview
+const PREVIEW_MAX_CHARS: usize = 600;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PreviewParams {
+    /// URL of an indexed page
+    url: String,
+}
+
+/// What's indexed for a page, for showing it without loading the page itself
+#[derive(Serialize, ToSchema)]
+pub struct Preview {
+    pub url: String,
+    pub title: String,
+    /// Where the page is on its site, like `docs.python.org › 3.13 › library › asyncio`
+    pub breadcrumb: String,
+    /// Start of the page's body
+    pub text: String,
+    /// Start of the page's body as HTML, with everything from the page escaped
+    pub html: String,
+}
+
+/// `GET /preview?url=...`: get the title, breadcrumb, and start of the body of an indexed page
+///
+/// This is what's stored in the index, so it's as of when the page was last crawled.
+#[utoipa::path(
+    get,
+    path = "/preview",
+    params(PreviewParams),
+    responses(
+        (status = 200, description = "The page's preview", body = Preview),
+        (status = 404, description = "The page isn't indexed"),
+    ),
+)]
+pub async fn preview(
+    State(st): State<AppState>,
+    Query(params): Query<PreviewParams>,
+) -> Result<Json<Preview>, AppError> {
+    // Blocked pages stay in the index until they're recrawled, but they're never shown
+    if !st.config().filter.allows_str(&params.url) {
+        return Err(AppError::not_found());
+    }
+    let Some(doc) = st.index.get(&params.url)? else {
+        return Err(AppError::not_found());
+    };
+
+    let field = |name: &str| -> Result<String, AppError> {
+        Ok(doc
+            .get_first(st.schema.get_field(name)?)
+            .and_then(|value| value.as_str())
+            .map(sanitize::text)
+            .unwrap_or_default())
+    };
+    let text = search::truncate(&field("body")?, PREVIEW_MAX_CHARS);
+
+    Ok(Json(Preview {
+        url: params.url,
+        title: field("title")?,
+        breadcrumb: field("breadcrumb")?,
+        html: format!("<p>{}</p>", escape_html(&text)),
+        text,
+    }))
+}
@@ -0,0 +1,394 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::sanitize;
+
+/// Longest word that's split into the words it's run together from, since that takes a lookup
+/// for every way of splitting it
+const MAX_SEGMENTED_LEN: usize = 40;
+
+/// How queries are cleaned up before they're parsed and embedded
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct QueryConfig {
+    /// Lowercase queries, so `HashMap` and `hashmap` get the same embedding
+    ///
+    /// This also turns the `AND`, `OR`, and `NOT` operators into plain words.
+    pub lowercase: bool,
+    /// Also search for the words in identifiers like `BufReader::read_line`, see
+    /// [expand_identifiers]
+    pub split_identifiers: bool,
+}
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            lowercase: false,
+            split_identifiers: true,
+        }
+    }
+}
+
+/// Normalize a query, so queries that look the same are searched for the same way
+///
+/// The query is put in Unicode NFC, so an accented letter typed as one character or as a letter
+/// and a combining accent is the same. Curly quotes and typographic dashes (which text editors
+/// and phones like to swap in) are folded into their ASCII counterparts, so quoted phrases and
+/// exclusions still work. Invisible characters are dropped and runs of whitespace collapsed.
+pub fn normalize(q: &str, config: &QueryConfig) -> String {
+    let folded: String = q.nfc().filter_map(fold).collect();
+    let q = sanitize::text(&folded);
+
+    if config.lowercase {
+        q.to_lowercase()
+    } else {
+        q
+    }
+}
+
+/// Fold a character into the one it's standing in for, or drop it if it's invisible
+fn fold(c: char) -> Option<char> {
+    Some(match c {
+        // ‘ ’ ‚ ‛ ′
+        '\u{2018}'..='\u{201B}' | '\u{2032}' => '\'',
+        // “ ” „ ‟ « » ″
+        '\u{201C}'..='\u{201F}' | '\u{00AB}' | '\u{00BB}' | '\u{2033}' => '"',
+        // Hyphens, dashes, and minus signs
+        '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE58}' | '\u{FE63}' | '\u{FF0D}' => '-',
+        // Zero-width spaces and joiners, and byte order marks
+        '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => return None,
+        c => c,
+    })
+}
+
+/// Rewrite the identifiers in a query so they also match the words they're made of
+///
+/// Identifiers are split at `::`, underscores, and dots, and where the case changes (so
+/// `BufReader::read_line` is `buf reader read line`). A lowercase word that isn't in the index
+/// is split into words that are, if it can be (so `readtostring` is `read to string`).
+///
+/// The identifier is kept as a boosted phrase, so pages with the identifier itself still rank
+/// first, and pages with all of its words match too. `known` checks whether a (lowercase) word
+/// is in the index. Quoted phrases and words using query syntax are left alone.
+pub fn expand_identifiers(q: &str, config: &QueryConfig, known: impl Fn(&str) -> bool) -> String {
+    if !config.split_identifiers {
+        return q.to_string();
+    }
+
+    tokens(q)
+        .map(|token| match identifier_words(token, &known) {
+            Some(words) => format!("(\"{token}\"^2 (+{}))", words.join(" +")),
+            None => token.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Map the search operators in a query onto the fields they search
+///
+/// `intitle:` only matches words (or a quoted phrase) in the title, and `inurl:` only matches
+/// them in the URL. Either can be excluded with `-`, like `-inurl:nightly`. Values with
+/// punctuation in them, like `inurl:docs.rs/tokio`, are searched for as a phrase.
+pub fn map_operators(q: &str) -> String {
+    tokens(q)
+        .filter_map(|token| {
+            let (prefix, rest) = split_prefix(token);
+            let Some((field, value)) = operator(rest) else {
+                return Some(token.to_string());
+            };
+            if value.is_empty() {
+                return None;
+            }
+
+            let plain = value.chars().all(char::is_alphanumeric);
+            Some(if plain || value.starts_with('"') {
+                format!("{prefix}{field}:{value}")
+            } else {
+                format!("{prefix}{field}:\"{}\"", value.replace('"', ""))
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the words of a query to generate its embedding from, leaving out the query syntax
+///
+/// Excluded words and `inurl:` values (which are usually bits of paths, not words about the
+/// page) are left out, and `intitle:` values and phrases are kept as plain words.
+pub fn embedding_text(q: &str) -> String {
+    let mut words = Vec::new();
+    let mut urls = Vec::new();
+    let mut tokens = tokens(q);
+    while let Some(token) = tokens.next() {
+        match token {
+            "AND" | "OR" => continue,
+            "NOT" => {
+                tokens.next();
+                continue;
+            }
+            _ => {}
+        }
+
+        let (prefix, rest) = split_prefix(token);
+        if prefix == "-" {
+            continue;
+        }
+        let (is_url, value) = match operator(rest) {
+            Some((field, value)) => (field == "url", value),
+            None => (false, rest),
+        };
+        let value = value.replace(['"', '(', ')'], "");
+        if value.is_empty() {
+            continue;
+        }
+        if is_url {
+            urls.push(value);
+        } else {
+            words.push(value);
+        }
+    }
+
+    // Something has to be embedded, even if the query is only `inurl:` values
+    if words.is_empty() {
+        urls.join(" ")
+    } else {
+        words.join(" ")
+    }
+}
+
+/// Split a query into its words and quoted phrases, keeping the syntax attached to them (like
+/// `-word` or `intitle:"a phrase"`)
+fn tokens(q: &str) -> impl Iterator<Item = &str> {
+    let mut rest = q.trim_start();
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        let mut in_phrase = false;
+        let len = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    in_phrase = !in_phrase;
+                }
+                !in_phrase && c.is_whitespace()
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (token, after) = rest.split_at(len);
+        rest = after.trim_start();
+        Some(token)
+    })
+}
+
+/// Split the `+` or `-` off the start of a word
+fn split_prefix(token: &str) -> (&str, &str) {
+    match token.strip_prefix(['+', '-']) {
+        Some(rest) => (&token[..1], rest),
+        None => ("", token),
+    }
+}
+
+/// Get the field a word with an operator (like `intitle:tokio`) searches and its value
+fn operator(token: &str) -> Option<(&'static str, &str)> {
+    if let Some(value) = token.strip_prefix("intitle:") {
+        Some(("title", value))
+    } else {
+        token.strip_prefix("inurl:").map(|value| ("url", value))
+    }
+}
+
+/// Get the lowercase words an identifier is made of, or [None] if it isn't one
+fn identifier_words(token: &str, known: &impl Fn(&str) -> bool) -> Option<Vec<String>> {
+    // Anything else is query syntax, like `+`, `-`, `"`, `field:`, or `^2`
+    if !token
+        .replace("::", "")
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    {
+        return None;
+    }
+
+    let mut words = Vec::new();
+    for part in token.split(|c: char| !c.is_alphanumeric()) {
+        for word in split_case(part) {
+            let lower = word.to_lowercase();
+            // Only words that are all lowercase could have been run together
+            match (lower == part).then(|| segment(&lower, known)).flatten() {
+                Some(segments) => words.extend(segments),
+                None => words.push(lower),
+            }
+        }
+    }
+    words.retain(|word| !word.is_empty());
+
+    (words.len() > 1).then_some(words)
+}
+
+/// Split a word where its case changes, like `HTTPServer` into `HTTP` and `Server`
+fn split_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_lower = chars
+            .get(i + 1)
+            .is_some_and(|(_, next)| next.is_lowercase());
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower));
+        if boundary {
+            parts.push(&word[start..at]);
+            start = at;
+        }
+    }
+    parts.push(&word[start..]);
+
+    parts
+}
+
+/// Split a word that isn't in the index into the fewest words that are, if it can be
+fn segment(word: &str, known: &impl Fn(&str) -> bool) -> Option<Vec<String>> {
+    let bounds: Vec<usize> = word
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([word.len()])
+        .collect();
+    let n = bounds.len() - 1;
+    if !(2..=MAX_SEGMENTED_LEN).contains(&n) || known(word) {
+        return None;
+    }
+
+    // best[i] is the fewest words the first i characters split into, and where the last starts
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; n + 1];
+    best[0] = Some((0, 0));
+    for end in 1..=n {
+        for start in 0..end {
+            // Single letters are in the index too, but almost never what was meant
+            if end - start < 2 {
+                continue;
+            }
+            let Some((count, _)) = best[start] else {
+                continue;
+            };
+            let better = best[end].map_or(true, |(best_count, _)| count + 1 < best_count);
+            if better && known(&word[bounds[start]..bounds[end]]) {
+                best[end] = Some((count + 1, start));
+            }
+        }
+    }
+
+    best[n]?;
+    let mut words = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let (_, start) = best[end]?;
+        words.push(word[bounds[start]..bounds[end]].to_string());
+        end = start;
+    }
+    words.reverse();
+
+    Some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known(word: &str) -> bool {
+        ["read", "to", "string", "line", "buf", "reader"].contains(&word)
+    }
+
+    fn expand(q: &str) -> String {
+        expand_identifiers(q, &QueryConfig::default(), known)
+    }
+
+    #[test]
+    fn splits_run_together_words() {
+        assert_eq!(
+            expand("readtostring"),
+            r#"("readtostring"^2 (+read +to +string))"#
+        );
+        // Words that can't be split into known ones are left alone
+        assert_eq!(expand("tokio"), "tokio");
+    }
+
+    #[test]
+    fn splits_paths_and_case_changes() {
+        assert_eq!(
+            expand("BufReader::read_line"),
+            r#"("BufReader::read_line"^2 (+buf +reader +read +line))"#
+        );
+        assert_eq!(expand("HTTPServer"), r#"("HTTPServer"^2 (+http +server))"#);
+    }
+
+    #[test]
+    fn leaves_operators_alone() {
+        assert_eq!(
+            expand("tokio AND serde OR NOT axum"),
+            "tokio AND serde OR NOT axum"
+        );
+        assert_eq!(expand("-readtostring +tokio"), "-readtostring +tokio");
+    }
+
+    #[test]
+    fn leaves_phrases_alone() {
+        assert_eq!(
+            expand(r#""BufReader::read_line" docs"#),
+            r#""BufReader::read_line" docs"#
+        );
+    }
+
+    #[test]
+    fn expanding_can_be_turned_off() {
+        let config = QueryConfig {
+            split_identifiers: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_identifiers("BufReader::read_line", &config, known),
+            "BufReader::read_line"
+        );
+    }
+
+    #[test]
+    fn maps_operators_to_fields() {
+        assert_eq!(map_operators("intitle:tokio spawn"), "title:tokio spawn");
+        assert_eq!(
+            map_operators(r#"intitle:"async fn""#),
+            r#"title:"async fn""#
+        );
+        assert_eq!(map_operators("tokio -inurl:nightly"), "tokio -url:nightly");
+        assert_eq!(
+            map_operators("inurl:docs.rs/tokio"),
+            r#"url:"docs.rs/tokio""#
+        );
+    }
+
+    #[test]
+    fn drops_empty_operators() {
+        assert_eq!(map_operators("intitle: spawn"), "spawn");
+        assert_eq!(map_operators("tokio AND serde"), "tokio AND serde");
+    }
+
+    #[test]
+    fn embeds_words_without_syntax() {
+        assert_eq!(
+            embedding_text(r#"intitle:"async fn" -inurl:nightly tokio"#),
+            "async fn tokio"
+        );
+        assert_eq!(
+            embedding_text(r#""hash map" -btree (entry)"#),
+            "hash map entry"
+        );
+        assert_eq!(
+            embedding_text("tokio AND serde NOT nightly OR axum"),
+            "tokio serde axum"
+        );
+    }
+
+    #[test]
+    fn embeds_urls_if_theres_nothing_else() {
+        assert_eq!(embedding_text("inurl:docs.rs/tokio"), "docs.rs/tokio");
+        assert_eq!(embedding_text("inurl:docs.rs spawn"), "spawn");
+    }
+}
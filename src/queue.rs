@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    admin::CrawlParams,
+    authority,
+    crawldb::{FailedFetch, Fetch},
+    crawler::{self, CrawlTarget},
+    error::AppError,
+    ingest::{self, DocumentReq},
+    title::TitleRule,
+    AppState,
+};
+
+/// How long a worker has to crawl a target before it's given to another worker
+pub const LEASE_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// A target handed out to a crawl worker
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Lease {
+    /// ID to report the target as done with
+    pub id: u64,
+    pub target: CrawlTarget,
+    /// Rules for cleaning up titles, so workers embed the same titles the primary would
+    #[serde(default)]
+    pub title_rules: Vec<TitleRule>,
+}
+
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    pending: VecDeque<CrawlTarget>,
+    leased: HashMap<u64, (CrawlTarget, Instant)>,
+}
+
+/// Targets waiting to be crawled by crawl workers
+#[derive(Clone, Default)]
+pub struct WorkQueue {
+    queue: Arc<Mutex<Queue>>,
+}
+impl WorkQueue {
+    /// Queue targets to be crawled, skipping any that are already queued or being crawled
+    ///
+    /// This returns how many were queued.
+    pub fn push(&self, targets: impl IntoIterator<Item = CrawlTarget>) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+
+        let mut queued = 0;
+        for target in targets {
+            let exists = queue.pending.iter().any(|t| t.url == target.url)
+                || queue.leased.values().any(|(t, _)| t.url == target.url);
+            if !exists {
+                queue.pending.push_back(target);
+                queued += 1;
+            }
+        }
+
+        queued
+    }
+
+    /// Hand out the next target, if there is one
+    ///
+    /// Targets whose lease ran out (because their worker died or is stuck) are handed out
+    /// again first.
+    pub fn lease(&self) -> Option<Lease> {
+        let mut queue = self.queue.lock().unwrap();
+
+        let now = Instant::now();
+        let expired: Vec<_> = queue
+            .leased
+            .iter()
+            .filter(|(_, (_, leased_at))| now.duration_since(*leased_at) > LEASE_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some((target, _)) = queue.leased.remove(&id) {
+                warn!(id, url = %target.url, "crawl lease expired, queueing target again");
+                queue.pending.push_front(target);
+            }
+        }
+
+        let target = queue.pending.pop_front()?;
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.leased.insert(id, (target.clone(), now));
+
+        Some(Lease {
+            id,
+            target,
+            title_rules: Vec::new(),
+        })
+    }
+
+    /// Get the target leased out as `id`, if the lease is still current
+    pub fn leased(&self, id: u64) -> Option<CrawlTarget> {
+        let queue = self.queue.lock().unwrap();
+        queue.leased.get(&id).map(|(target, _)| target.clone())
+    }
+
+    /// Mark a leased target as crawled, returning `false` if there's no such lease
+    pub fn finish(&self, id: u64) -> bool {
+        self.queue.lock().unwrap().leased.remove(&id).is_some()
+    }
+
+    /// Whether every target has been crawled
+    fn is_empty(&self) -> bool {
+        let queue = self.queue.lock().unwrap();
+        queue.pending.is_empty() && queue.leased.is_empty()
+    }
+
+    fn status(&self) -> QueueStatus {
+        let queue = self.queue.lock().unwrap();
+        QueueStatus {
+            pending: queue.pending.len(),
+            leased: queue.leased.len(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct QueueStatus {
+    /// Targets waiting for a worker
+    pending: usize,
+    /// Targets being crawled by workers
+    leased: usize,
+}
+
+/// `POST /admin/queue`: queue the configured targets for crawl workers
+pub async fn enqueue(
+    State(st): State<AppState>,
+    Query(params): Query<CrawlParams>,
+) -> (StatusCode, Json<QueueStatus>) {
+    let targets = st
+        .config()
+        .targets
+        .iter()
+        .filter(|t| params.target.as_ref().map_or(true, |f| t.matches(f)))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let queued = st.queue.push(targets);
+    info!(queued, "queued targets for crawl workers");
+
+    (StatusCode::ACCEPTED, Json(st.queue.status()))
+}
+
+/// `GET /admin/queue`: get how many targets are queued and being crawled
+pub async fn queue_status(State(st): State<AppState>) -> Json<QueueStatus> {
+    Json(st.queue.status())
+}
+
+/// `POST /admin/queue/lease`: take the next target to crawl
+pub async fn lease(State(st): State<AppState>) -> Response {
+    match st.queue.lease() {
+        Some(mut lease) => {
+            lease.title_rules = st.config().title_rules.clone();
+            info!(id = lease.id, url = %lease.target.url, "leased target to a crawl worker");
+            Json(lease).into_response()
+        }
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Pages a crawl worker crawled for its target, and how fetching each one went
+#[derive(Serialize, Deserialize, Default)]
+pub struct WorkerPages {
+    /// Pages to index
+    pub docs: Vec<DocumentReq>,
+    /// Pages that were fetched, for the crawl database
+    pub fetched: Vec<Fetch>,
+    /// Pages that couldn't be fetched, for the crawl database
+    pub failed: Vec<FailedFetch>,
+}
+
+fn no_lease() -> AppError {
+    AppError::new(StatusCode::NOT_FOUND, "no target has that lease")
+}
+
+/// `POST /admin/queue/:id/pages`: send pages crawled for a leased target
+///
+/// Pages are committed in batches, the same as when the server crawls, rather than with every
+/// request, and the rest are committed when the target is done.
+pub async fn pages(
+    State(st): State<AppState>,
+    Path(id): Path<u64>,
+    Json(pages): Json<WorkerPages>,
+) -> Result<StatusCode, AppError> {
+    let target = st.queue.leased(id).ok_or_else(no_lease)?;
+
+    // Blocked pages aren't recorded, the same as when the server crawls
+    let config = st.config();
+    for fetch in &pages.fetched {
+        if config.filter.allows_str(&fetch.url) {
+            st.crawldb.record_fetch(fetch, &target.source)?;
+        }
+    }
+    for failed in &pages.failed {
+        if config.filter.allows_str(&failed.url) {
+            st.crawldb
+                .failed(&failed.url, &target.source, &failed.error)?;
+        }
+    }
+
+    let docs = ingest::documents(&st, pages.docs).await?;
+    let count = docs.len();
+    let staged = st.index.stage(docs).map_err(|err| err.to_string())?;
+    if staged >= crawler::COMMIT_EVERY {
+        st.index.commit().map_err(|err| err.to_string())?;
+    }
+    debug!(id, pages = count, "indexed pages from a crawl worker");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/queue/:id/done`: report a leased target as crawled
+///
+/// Its pages are committed, and once every target is crawled, page authority is recomputed.
+pub async fn finish(
+    State(st): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, AppError> {
+    if !st.queue.finish(id) {
+        return Err(no_lease());
+    }
+    info!(id, "crawl worker finished its target");
+    st.index.commit().map_err(|err| err.to_string())?;
+
+    if st.queue.is_empty() {
+        // The crawl changed the link graph
+        tokio::spawn(async move { authority::refresh(&st).await });
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
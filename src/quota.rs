@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{extract::State, Json};
+
+use crate::AppState;
+
+/// Requests made with a key
+#[derive(Default)]
+struct Counter {
+    /// Minute the minute count is for, in minutes since the Unix epoch
+    minute: u64,
+    minute_count: u64,
+    /// Day the day count is for, in days since the Unix epoch (UTC)
+    day: u64,
+    day_count: u64,
+    total: u64,
+    /// Requests turned away for going over a quota
+    rejected: u64,
+    /// When the key was last used, in seconds since the Unix epoch
+    last_used: u64,
+}
+
+/// Limits on how many requests a key can make
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    pub per_minute: Option<u64>,
+    pub per_day: Option<u64>,
+}
+
+/// How many API requests each key made, for quotas and for seeing who's responsible for load
+///
+/// This is kept in memory, so it starts over when the server restarts.
+#[derive(Default)]
+pub struct Usage {
+    keys: Mutex<HashMap<String, Counter>>,
+}
+impl Usage {
+    /// Count a request made with the key named `name`, unless it's over one of its quotas
+    ///
+    /// If it is, this returns how long until the quota resets.
+    pub fn check(&self, name: &str, limits: Limits) -> Result<(), Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (minute, day) = (now / 60, now / 86_400);
+
+        let mut keys = self.keys.lock().unwrap();
+        let counter = keys.entry(name.to_string()).or_default();
+        if counter.minute != minute {
+            counter.minute = minute;
+            counter.minute_count = 0;
+        }
+        if counter.day != day {
+            counter.day = day;
+            counter.day_count = 0;
+        }
+        counter.last_used = now;
+
+        if limits.per_day.is_some_and(|max| counter.day_count >= max) {
+            counter.rejected += 1;
+            return Err(Duration::from_secs((day + 1) * 86_400 - now));
+        }
+        if limits
+            .per_minute
+            .is_some_and(|max| counter.minute_count >= max)
+        {
+            counter.rejected += 1;
+            return Err(Duration::from_secs((minute + 1) * 60 - now));
+        }
+
+        counter.minute_count += 1;
+        counter.day_count += 1;
+        counter.total += 1;
+        Ok(())
+    }
+}
+
+/// Usage of a key, as reported by the admin API
+#[derive(Serialize)]
+pub struct KeyUsage {
+    name: String,
+    /// Requests made this minute
+    minute: u64,
+    /// Requests made today (UTC)
+    today: u64,
+    /// Requests made since the server started
+    total: u64,
+    /// Requests turned away for going over a quota
+    rejected: u64,
+    /// When the key was last used, in seconds since the Unix epoch
+    last_used: u64,
+    per_minute: Option<u64>,
+    per_day: Option<u64>,
+}
+
+/// `GET /admin/usage`: get how many requests each API key made, busiest first today
+pub async fn usage(State(st): State<AppState>) -> Json<Vec<KeyUsage>> {
+    let config = st.config();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut usage: Vec<_> = st
+        .usage
+        .keys
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, counter)| {
+            let limits = config.auth.limits(name);
+            KeyUsage {
+                name: name.clone(),
+                minute: if counter.minute == now / 60 {
+                    counter.minute_count
+                } else {
+                    0
+                },
+                today: if counter.day == now / 86_400 {
+                    counter.day_count
+                } else {
+                    0
+                },
+                total: counter.total,
+                rejected: counter.rejected,
+                last_used: counter.last_used,
+                per_minute: limits.per_minute,
+                per_day: limits.per_day,
+            }
+        })
+        .collect();
+    usage.sort_by(|a, b| b.today.cmp(&a.today).then_with(|| a.name.cmp(&b.name)));
+
+    Json(usage)
+}
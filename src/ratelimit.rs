@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::AppState;
+
+/// Per-client rate limits for searching
+///
+/// Every search runs the embedding model, so one client searching too often can pin the CPU.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Searches each client is allowed per second, on average
+    pub per_second: f64,
+    /// Searches each client can make in a quick burst
+    pub burst: f64,
+    /// Identify clients by `X-Forwarded-For`, when running behind a reverse proxy
+    pub trust_forwarded_for: bool,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            per_second: 2.0,
+            burst: 10.0,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+/// Start cleaning up idle buckets once there are this many clients
+const MAX_IDLE_BUCKETS: usize = 10_000;
+/// Least time between cleanups, so a flood of new clients doesn't make every request scan
+/// every bucket
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Token bucket rate limiter, keyed by client IP
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    /// When idle buckets were last cleaned up
+    pruned_at: Mutex<Option<Instant>>,
+}
+impl RateLimiter {
+    /// Take `cost` tokens from the client's bucket
+    ///
+    /// As long as there's a token left, the bucket can go below empty, so a batch bigger than
+    /// the burst can still be made, but the client has to wait longer afterwards. If the bucket
+    /// is empty, this returns how long until the next token is available.
+    fn check(&self, ip: IpAddr, config: &RateLimitConfig, cost: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Buckets that have refilled completely are the same as new ones, so drop them
+        let mut pruned_at = self.pruned_at.lock().unwrap();
+        let due = pruned_at.map_or(true, |at| now.duration_since(at) >= PRUNE_INTERVAL);
+        if buckets.len() >= MAX_IDLE_BUCKETS && due {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.last).as_secs_f64() * config.per_second
+                    < config.burst
+            });
+            *pruned_at = Some(now);
+        }
+        drop(pruned_at);
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: config.burst,
+            last: now,
+        });
+
+        // Refill the bucket for the time since it was last used
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * config.per_second).min(config.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / config.per_second,
+            ))
+        }
+    }
+}
+
+/// Get the IP of the client that made a request, given its headers and the address it
+/// connected from
+pub fn client_ip(
+    headers: &HeaderMap,
+    addr: Option<SocketAddr>,
+    config: &RateLimitConfig,
+) -> IpAddr {
+    let forwarded = config
+        .trust_forwarded_for
+        .then(|| headers.get("x-forwarded-for"))
+        .flatten()
+        .and_then(|xff| xff.to_str().ok())
+        .and_then(|xff| xff.split(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+
+    forwarded
+        .or_else(|| addr.map(|addr| addr.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Charge a client for `searches` searches, or get the response telling it to slow down
+pub fn take(
+    st: &AppState,
+    headers: &HeaderMap,
+    addr: Option<SocketAddr>,
+    searches: usize,
+) -> Result<(), Response> {
+    let config = st.config();
+    let limits = &config.rate_limit;
+    if !limits.enabled || limits.per_second <= 0.0 {
+        return Ok(());
+    }
+
+    let ip = client_ip(headers, addr, limits);
+    st.limiter
+        .check(ip, limits, searches as f64)
+        .map_err(|retry_after| {
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(
+                    header::RETRY_AFTER,
+                    retry_after.as_secs().max(1).to_string(),
+                )],
+                "too many requests, slow down",
+            )
+                .into_response()
+        })
+}
+
+/// Middleware limiting how often each client can search
+pub async fn limit(State(st): State<AppState>, req: Request, next: Next) -> Response {
+    let addr = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    match take(&st, req.headers(), addr, 1) {
+        Ok(()) => next.run(req).await,
+        Err(res) => res,
+    }
+}
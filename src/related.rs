@@ -0,0 +1,69 @@
+use std::collections::{HashMap, HashSet};
+
+use tantivy::{schema::Field, Searcher, Term};
+
+use crate::query;
+
+/// Most related searches suggested for a query
+const MAX_RELATED: usize = 5;
+/// Fewest top results a word has to be in the titles of to be suggested
+const MIN_RESULTS: usize = 2;
+
+/// Suggest searches related to `q`, from words that come up in the titles of its top results
+///
+/// Words that are in the titles of several results, but rare across the whole index, are the
+/// most telling (like `entry` for `hashmap`), so each is tacked onto the query as a way to narrow
+/// it down. Words that are in most titles (like `rust` on docs.rs) barely count.
+///
+/// Only the results are used, since what people search for isn't kept anywhere that's shared
+/// between browsers.
+pub fn related<'a>(
+    searcher: &Searcher,
+    title_field: Field,
+    q: &str,
+    titles: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let searched: HashSet<String> = words(&query::embedding_text(q)).collect();
+
+    // How many of the titles each word is in
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for title in titles {
+        let title_words: HashSet<String> = words(title).collect();
+        for word in title_words {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+
+    let total = searcher.num_docs() as f64;
+    let mut scored: Vec<(f64, String)> = counts
+        .into_iter()
+        .filter(|(word, count)| {
+            *count >= MIN_RESULTS
+                && word.chars().count() > 2
+                && !word.chars().all(|c| c.is_numeric())
+                && !searched.contains(word)
+        })
+        .filter_map(|(word, count)| {
+            let freq = searcher
+                .doc_freq(&Term::from_field_text(title_field, &word))
+                .ok()?;
+            let idf = (total / (1 + freq) as f64).ln();
+            (idf > 0.0).then_some((count as f64 * idf, word))
+        })
+        .collect();
+    // Ties go to the word that sorts first, so the same results always get the same suggestions
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    scored
+        .into_iter()
+        .take(MAX_RELATED)
+        .map(|(_, word)| format!("{q} {word}"))
+        .collect()
+}
+
+/// Split text into lowercase words, the same way the default tokenizer does
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
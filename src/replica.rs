@@ -0,0 +1,224 @@
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fs::{self, File},
+    io::Write,
+    path::Path as FsPath,
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use spider::{reqwest::Client, url::Url};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::AppError,
+    index::{SearchIndex, INDEX_DIR},
+    AppState,
+};
+
+/// Where a replica syncs its index from
+#[derive(Deserialize, Clone)]
+pub struct ReplicaConfig {
+    /// URL of the primary (e.g. `https://search.example.com/`)
+    pub primary: String,
+    /// One of the primary's admin keys
+    pub key: String,
+    /// Seconds between syncs
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+fn default_interval() -> u64 {
+    5 * 60
+}
+
+/// The files making up the last commit of the index
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// Contents of the index's `meta.json`
+    meta: serde_json::Value,
+    files: Vec<IndexFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    name: String,
+    size: u64,
+}
+
+fn manifest(index: &SearchIndex) -> Result<Manifest, Box<dyn Error + Send + Sync>> {
+    let metas = index.metas()?;
+
+    let names: BTreeSet<_> = metas
+        .segments
+        .iter()
+        .flat_map(|segment| segment.list_files())
+        .filter_map(|path| path.to_str().map(str::to_string))
+        .collect();
+
+    let files = names
+        .into_iter()
+        .map(|name| {
+            let size = fs::metadata(FsPath::new(INDEX_DIR).join(&name))?.len();
+            Ok(IndexFile { name, size })
+        })
+        .collect::<Result<_, std::io::Error>>()?;
+
+    Ok(Manifest {
+        meta: serde_json::to_value(&metas)?,
+        files,
+    })
+}
+
+/// `GET /admin/replica/manifest`: list the files in the index, for replicas to sync
+pub async fn get_manifest(State(st): State<AppState>) -> Result<Json<Manifest>, AppError> {
+    Ok(Json(manifest(&st.index)?))
+}
+
+/// `GET /admin/replica/files/:name`: download a file from the index
+///
+/// Only files in the current manifest can be downloaded.
+pub async fn get_file(
+    State(st): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if !manifest(&st.index)?.files.iter().any(|f| f.name == name) {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "no file with that name is in the index",
+        ));
+    }
+
+    let file = tokio::fs::File::open(FsPath::new(INDEX_DIR).join(&name)).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        Body::from_stream(ReaderStream::new(file)),
+    ))
+}
+
+/// Whether an index file belongs to a segment (as opposed to `meta.json`, the lock files, or
+/// our own state, like cached favicons)
+///
+/// Segment files are named after the segment's ID, which is 32 hex digits.
+fn is_segment_file(name: &str) -> bool {
+    name.len() > 33
+        && name.as_bytes()[32] == b'.'
+        && name[..32].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Sync the index from the primary, returning how many files were downloaded
+async fn sync(
+    client: &Client,
+    config: &ReplicaConfig,
+    index: &SearchIndex,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let primary = Url::parse(&config.primary)?;
+    let dir = FsPath::new(INDEX_DIR);
+
+    let manifest: Manifest = {
+        let res = client
+            .get(primary.join("admin/replica/manifest")?)
+            .bearer_auth(&config.key)
+            .send()
+            .await?
+            .error_for_status()?;
+        serde_json::from_slice(&res.bytes().await?)?
+    };
+
+    // Segment files never change once they're written, so only new ones need downloading
+    let mut downloaded = 0;
+    for file in &manifest.files {
+        let path = dir.join(&file.name);
+        if fs::metadata(&path).is_ok_and(|m| m.len() == file.size) {
+            continue;
+        }
+
+        let mut res = client
+            .get(primary.join(&format!("admin/replica/files/{}", file.name))?)
+            .bearer_auth(&config.key)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Download to a temporary file, so a partial download is never mistaken for the file
+        let part = dir.join(format!("{}.part", file.name));
+        let mut out = File::create(&part)?;
+        while let Some(chunk) = res.chunk().await? {
+            out.write_all(&chunk)?;
+        }
+        out.sync_all()?;
+        fs::rename(&part, &path)?;
+
+        downloaded += 1;
+    }
+
+    // Even when no segments are new, `meta.json` can be out of date (like after a crash
+    // mid-sync), so it's compared with the primary's too
+    let meta = dir.join("meta.json");
+    let current: Option<serde_json::Value> = fs::read(&meta)
+        .ok()
+        .and_then(|current| serde_json::from_slice(&current).ok());
+    if downloaded == 0 && current.as_ref() == Some(&manifest.meta) {
+        return Ok(0);
+    }
+
+    // Switch to the new commit, then delete the segments it doesn't use
+    let part = dir.join("meta.json.part");
+    fs::write(&part, serde_json::to_string_pretty(&manifest.meta)? + "\n")?;
+    fs::rename(&part, &meta)?;
+    index.reader().reload()?;
+
+    let keep: BTreeSet<_> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if is_segment_file(name) && !keep.contains(name) {
+            fs::remove_file(dir.join(name))?;
+        }
+    }
+
+    Ok(downloaded)
+}
+
+/// Keep the index in sync with the primary, until shutdown
+///
+/// The replica config is read again before every sync, so the interval and key can be changed
+/// by reloading the config.
+pub async fn sync_loop(st: AppState) {
+    let client = match Client::builder()
+        .timeout(Duration::from_secs(60 * 60))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            error!("couldn't create an HTTP client for syncing: {err}");
+            return;
+        }
+    };
+
+    while let Some(config) = st.config().replica.clone() {
+        match sync(&client, &config, &st.index).await {
+            Ok(0) => debug!("index is already in sync with the primary"),
+            Ok(files) => info!(
+                files,
+                docs = st.reader.searcher().num_docs(),
+                "synced index from the primary"
+            ),
+            // Files can be merged away on the primary mid-sync, so this is retried next time
+            Err(err) => warn!("couldn't sync index from the primary: {err}"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.interval)) => {}
+            _ = st.shutdown.cancelled() => return,
+        }
+    }
+}
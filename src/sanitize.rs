@@ -0,0 +1,130 @@
+use std::{borrow::Cow, ops::Range};
+
+use spider::url::Url;
+use tantivy::Snippet;
+use tera::escape_html;
+
+/// Clean up text from a crawled page, like a title or description
+///
+/// Control characters are dropped and runs of whitespace are collapsed, so the text can't
+/// mangle the results page. This doesn't escape anything, since templates do that when they
+/// render it, and CSV exports go through [csv_cell].
+pub fn text(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace())
+        .map(|word| word.chars().filter(|c| !c.is_control()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Render a snippet as HTML, with the matched words in `<b>` tags
+///
+/// Everything from the page is escaped, so the `<b>` tags are the only markup in the result,
+/// and it's safe to render without escaping.
+pub fn snippet_html(snippet: &Snippet) -> String {
+    highlight_html(snippet.fragment(), snippet.highlighted())
+}
+
+/// Escape `fragment` and put the `highlighted` byte ranges of it in `<b>` tags
+fn highlight_html(fragment: &str, highlighted: &[Range<usize>]) -> String {
+    let mut html = String::with_capacity(fragment.len());
+    let mut pos = 0;
+    for range in highlighted {
+        // Highlights shouldn't overlap, but don't repeat text if they do
+        let start = range.start.max(pos);
+        if start >= range.end {
+            continue;
+        }
+        let (Some(before), Some(matched)) =
+            (fragment.get(pos..start), fragment.get(start..range.end))
+        else {
+            continue;
+        };
+
+        html.push_str(&escape_html(before));
+        html.push_str("<b>");
+        html.push_str(&escape_html(matched));
+        html.push_str("</b>");
+        pos = range.end;
+    }
+    html.push_str(&escape_html(fragment.get(pos..).unwrap_or_default()));
+
+    html
+}
+
+/// Make text safe to put in a cell of a CSV export
+///
+/// Spreadsheets run cells that start with `=`, `+`, `-` or `@` as formulas (and some skip a tab
+/// or carriage return first), so a crawled title could run anything it likes when someone opens
+/// the export. Those cells get a `'` in front, which makes them plain text.
+pub fn csv_cell(text: &str) -> Cow<'_, str> {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{text}"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Check that a URL from a page (like its preview image) is a web URL
+///
+/// Anything else, like a `javascript:` or `data:` URL, is thrown out.
+pub fn web_url(url: &str) -> Option<String> {
+    Url::parse(url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_in_fragments() {
+        assert_eq!(
+            highlight_html("<script>alert(1)</script> hashmap", &[26..33]),
+            "&lt;script&gt;alert(1)&lt;&#x2F;script&gt; <b>hashmap</b>"
+        );
+    }
+
+    #[test]
+    fn escapes_markup_in_highlights() {
+        assert_eq!(
+            highlight_html("a <script> tag", &[2..10]),
+            "a <b>&lt;script&gt;</b> tag"
+        );
+    }
+
+    #[test]
+    fn overlapping_highlights_dont_repeat_text() {
+        assert_eq!(
+            highlight_html("hello world", &[0..5, 3..8]),
+            "<b>hello</b><b> wo</b>rld"
+        );
+        // A highlight inside the last one is dropped
+        assert_eq!(
+            highlight_html("hello world", &[0..8, 2..4]),
+            "<b>hello wo</b>rld"
+        );
+    }
+
+    #[test]
+    fn highlights_off_char_boundaries_are_skipped() {
+        // `é` is two bytes, so 2..3 ends in the middle of it
+        assert_eq!(highlight_html("héllo", &[2..3]), "héllo");
+        assert_eq!(highlight_html("héllo", &[0..2, 3..6]), "hé<b>llo</b>");
+    }
+
+    #[test]
+    fn highlights_past_the_end_are_skipped() {
+        assert_eq!(highlight_html("hello", &[3..10]), "hello");
+    }
+
+    #[test]
+    fn csv_cells_starting_with_formulas_are_escaped() {
+        assert_eq!(csv_cell("=HYPERLINK(\"x\")"), "'=HYPERLINK(\"x\")");
+        assert_eq!(csv_cell("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_cell("\t=1"), "'\t=1");
+        assert_eq!(csv_cell("HashMap in std"), "HashMap in std");
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use tantivy::schema::Value;
+use tera::Context;
+
+use crate::{
+    browser, crawler, error::AppError, feed::rfc3339, sanitize, store::JsonStore, AppState, Format,
+};
+
+/// A page saved from the results page
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedPage {
+    pub url: String,
+    pub title: String,
+    /// When the page was saved, as an RFC 3339 timestamp
+    pub saved_at: String,
+}
+
+/// Pages saved by each browser, newest first, keyed by the ID in the browser's cookie
+pub type SavedPages = JsonStore<HashMap<String, Vec<SavedPage>>>;
+
+/// Most pages each browser can save
+const MAX_PAGES: usize = 1000;
+/// Most browsers that can have saved pages, so clients making up IDs can't fill the disk
+const MAX_BROWSERS: usize = 10_000;
+
+/// Load the saved pages
+pub fn load() -> Result<SavedPages, Box<dyn std::error::Error>> {
+    JsonStore::load("saved.json")
+}
+
+#[derive(Deserialize)]
+pub struct SaveForm {
+    url: String,
+    /// Page to go back to afterwards
+    #[serde(rename = "return")]
+    back: Option<String>,
+}
+
+/// Redirect back to the page a form was sent from
+///
+/// Only paths on this instance are followed, so the form can't send people elsewhere.
+fn back_to(back: Option<String>) -> Redirect {
+    match back {
+        Some(back) if back.starts_with('/') && !back.starts_with("//") => Redirect::to(&back),
+        _ => Redirect::to("/saved"),
+    }
+}
+
+/// Get the URLs of the pages a browser has saved
+pub fn urls(st: &AppState, browser: Option<&str>) -> Vec<String> {
+    let Some(browser) = browser else {
+        return Vec::new();
+    };
+    st.saved.read(|saved| {
+        saved
+            .get(browser)
+            .map(|pages| pages.iter().map(|page| page.url.clone()).collect())
+            .unwrap_or_default()
+    })
+}
+
+/// `POST /saved`: save a page
+///
+/// Pages are saved for the browser that saves them, which needs the cookie the results page
+/// gives it.
+pub async fn save(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<SaveForm>,
+) -> Result<Redirect, AppError> {
+    let browser = browser::id(&headers)
+        .ok_or_else(|| AppError::bad_request("cookies are needed to save pages"))?;
+
+    // Take the title from the index, so only indexed pages can be saved
+    let doc = st
+        .index
+        .get(&form.url)?
+        .ok_or_else(|| AppError::bad_request("that page isn't in the index"))?;
+    let title = doc
+        .get_first(st.schema.get_field("title")?)
+        .and_then(|title| title.as_str())
+        .unwrap_or(&form.url)
+        .to_string();
+
+    st.saved.update(|saved| {
+        if !saved.contains_key(&browser) && saved.len() >= MAX_BROWSERS {
+            return Err(AppError::bad_request("no more pages can be saved"));
+        }
+        let pages = saved.entry(browser).or_default();
+        if pages.iter().any(|page| page.url == form.url) {
+            return Ok(());
+        }
+        if pages.len() >= MAX_PAGES {
+            return Err(AppError::bad_request(format!(
+                "at most {MAX_PAGES} pages can be saved"
+            )));
+        }
+
+        pages.insert(
+            0,
+            SavedPage {
+                url: form.url.clone(),
+                title,
+                saved_at: rfc3339(crawler::now()),
+            },
+        );
+        Ok(())
+    })??;
+
+    Ok(back_to(form.back))
+}
+
+/// `POST /saved/remove`: remove a page the browser saved
+pub async fn remove(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<SaveForm>,
+) -> Result<Redirect, AppError> {
+    if let Some(browser) = browser::id(&headers) {
+        st.saved.update(|saved| {
+            if let Some(pages) = saved.get_mut(&browser) {
+                pages.retain(|page| page.url != form.url);
+                if pages.is_empty() {
+                    saved.remove(&browser);
+                }
+            }
+        })?;
+    }
+
+    Ok(back_to(form.back))
+}
+
+#[derive(Deserialize)]
+pub struct SavedParams {
+    /// Download the saved pages as a file instead of showing them
+    #[serde(default)]
+    format: Format,
+}
+
+/// `GET /saved`: list the pages the browser saved
+pub async fn saved_page(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<SavedParams>,
+) -> Result<Response, AppError> {
+    let pages = browser::id(&headers)
+        .and_then(|browser| st.saved.read(|saved| saved.get(&browser).cloned()))
+        .unwrap_or_default();
+
+    Ok(match params.format {
+        Format::Html => {
+            let mut ctx = Context::new();
+            ctx.insert("pages", &pages);
+            Html(st.page_templates(&headers).render("saved.html", &ctx)?).into_response()
+        }
+        Format::Json => (
+            [(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"saved-pages.json\"",
+            )],
+            Json(pages),
+        )
+            .into_response(),
+        Format::Csv => {
+            let mut w = csv::Writer::from_writer(Vec::new());
+            for page in &pages {
+                w.serialize(SavedPage {
+                    url: sanitize::csv_cell(&page.url).into_owned(),
+                    title: sanitize::csv_cell(&page.title).into_owned(),
+                    saved_at: page.saved_at.clone(),
+                })?;
+            }
+            let csv = w.into_inner().map_err(|err| err.to_string())?;
+
+            (
+                [
+                    (header::CONTENT_TYPE, "text/csv"),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"saved-pages.csv\"",
+                    ),
+                ],
+                csv,
+            )
+                .into_response()
+        }
+    })
+}
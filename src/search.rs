@@ -0,0 +1,723 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    future::Future,
+    ops::Bound,
+    time::{Duration, Instant},
+};
+
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, Occur, Query, RangeQuery, TermQuery},
+    schema::{IndexRecordOption, Schema, Value},
+    DateTime, SnippetGenerator, TantivyDocument, Term,
+};
+use time::{Date, Month};
+
+use tracing::Instrument;
+use utoipa::ToSchema;
+
+use crate::{calc, crawldb, crawler, favicon, query, related, sanitize, AppState};
+
+/// A search result
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Res {
+    pub url: String,
+    pub title: String,
+    /// URL of the favicon for the result's domain
+    pub favicon: Option<String>,
+    /// Where the page is on its site, like `docs.python.org › 3.13 › library › asyncio`
+    pub breadcrumb: String,
+    pub snippet: String,
+    /// Snippet without any highlighting markup
+    pub snippet_text: String,
+    /// What kind of page this is, like `article`, from its OpenGraph or schema.org metadata
+    pub page_type: Option<String>,
+    /// URL of the page's preview image, from its OpenGraph or schema.org metadata
+    pub image: Option<String>,
+    /// Relevance from 0 to 1, for thresholding and display
+    pub score: f32,
+    /// Whether the score is low enough that this is probably a poor match
+    pub weak: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explain: Option<Explain>,
+}
+
+/// Breakdown of how a result was ranked
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Explain {
+    /// BM25 score from tantivy
+    pub bm25: f32,
+    /// Cosine similarity between the query and title embeddings
+    pub similarity: f32,
+    /// Times the result was clicked for this query
+    pub clicks: u32,
+    /// Authority of the page from the link graph, where the average page's is 1
+    pub authority: f32,
+    /// Final score the results are sorted by (similarity times the source, click, and
+    /// authority boosts)
+    pub fused: f32,
+    /// tantivy's explanation of the BM25 score
+    pub query: String,
+}
+
+/// Answer extracted from the top result, shown above the results
+#[derive(Serialize, Clone, ToSchema)]
+pub struct Answer {
+    pub url: String,
+    pub title: String,
+    pub snippet: String,
+}
+
+/// Results scoring below this are shown as weak matches
+const WEAK_MATCH_SCORE: f32 = 0.5;
+
+/// Minimum cosine similarity for the top result to be used as an [Answer]
+const ANSWER_MIN_SIMILARITY: f32 = 0.85;
+/// Snippet budget for answers, which should be about a paragraph long
+const ANSWER_MAX_CHARS: usize = 400;
+
+/// Snippets shorter than this are replaced by the page's description, if it has one
+const MIN_SNIPPET_CHARS: usize = 40;
+/// Descriptions used as snippets are cut to about the length of a snippet
+const DESCRIPTION_MAX_CHARS: usize = 200;
+
+/// Results of a search, and anything else shown with them
+#[derive(Serialize, ToSchema)]
+pub struct SearchRes {
+    pub query: String,
+    /// Language the results were limited to
+    pub lang: String,
+    /// Date the results were limited to pages crawled on or after, as it was given
+    pub after: Option<String>,
+    /// Date the results were limited to pages crawled before, as it was given
+    pub before: Option<String>,
+    /// Instant answer if the query is a calculation or unit conversion
+    pub calculation: Option<String>,
+    /// Answer from the top result, if it's similar enough to the query
+    pub answer: Option<Answer>,
+    /// Problems encountered while parsing the query
+    pub warnings: Vec<String>,
+    pub results: Vec<Res>,
+    /// Searches that narrow the query down, from words that come up in its top results
+    pub related: Vec<String>,
+    /// How long each stage of the search took, if it was asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+/// How long each stage of a search took
+///
+/// These are serialized in microseconds. The query embedding is usually generated while the
+/// index is searched, so the stages can add up to more than the total.
+#[derive(Serialize, Clone, Copy, Debug, Default, ToSchema)]
+pub struct Timings {
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub total: Duration,
+    /// Parsing the query
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub parse: Duration,
+    /// Retrieving the top documents by BM25
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub retrieve: Duration,
+    /// Loading the retrieved documents and their embeddings
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub fetch: Duration,
+    /// Generating the query embedding
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub embed: Duration,
+    /// Reranking by similarity and boosts
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub rerank: Duration,
+    /// Generating snippets (and the answer)
+    #[serde(serialize_with = "micros")]
+    #[schema(value_type = u64)]
+    pub snippets: Duration,
+}
+
+fn micros<S: serde::Serializer>(time: &Duration, ser: S) -> Result<S::Ok, S::Error> {
+    ser.serialize_u128(time.as_micros())
+}
+
+impl SearchRes {
+    /// Export the results as CSV, one row per result
+    pub fn to_csv(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        #[derive(Serialize)]
+        struct Row<'a> {
+            rank: usize,
+            title: Cow<'a, str>,
+            url: Cow<'a, str>,
+            score: f32,
+            snippet: Cow<'a, str>,
+        }
+
+        let mut w = csv::Writer::from_writer(Vec::new());
+        for (i, res) in self.results.iter().enumerate() {
+            w.serialize(Row {
+                rank: i + 1,
+                title: sanitize::csv_cell(&res.title),
+                url: sanitize::csv_cell(&res.url),
+                score: res.score,
+                snippet: sanitize::csv_cell(&res.snippet_text),
+            })?;
+        }
+
+        Ok(w.into_inner().map_err(|err| err.to_string())?)
+    }
+}
+
+/// Language results are limited to, unless another one is asked for
+pub const DEFAULT_LANG: &str = "en";
+
+/// Options that change how a search is performed
+#[derive(Clone)]
+pub struct SearchOptions {
+    /// Include a ranking explanation for each result
+    pub explain: bool,
+    /// Language code to limit results to, or `all` for every language
+    pub lang: String,
+    /// Include how long each stage of the search took
+    pub debug: bool,
+    /// Only include pages crawled on or after this date, see [parse_date]
+    pub after: Option<String>,
+    /// Only include pages crawled before this date, see [parse_date]
+    pub before: Option<String>,
+}
+
+/// Shortcuts for dates, and how many days back from today they are
+const RELATIVE_DATES: &[(&str, i64)] = &[
+    ("past_day", 1),
+    ("past_week", 7),
+    ("past_month", 30),
+    ("past_year", 365),
+];
+
+/// Parse a date to limit results by: a day like `2024-06-30`, or a shortcut like `past_year`
+pub fn parse_date(s: &str) -> Result<DateTime, String> {
+    let s = s.trim();
+    if let Some((_, days)) = RELATIVE_DATES.iter().find(|(name, _)| *name == s) {
+        let now = crawler::now().into_timestamp_secs();
+        return Ok(DateTime::from_timestamp_secs(now - days * 24 * 60 * 60));
+    }
+
+    let invalid = || format!("`{s}` isn't a date like 2024-06-30 or past_year, so it was ignored");
+    let mut parts = s.splitn(3, '-').map(|part| part.parse::<i32>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|month| Month::try_from(month).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+
+    Ok(DateTime::from_timestamp_secs(
+        date.midnight().assume_utc().unix_timestamp(),
+    ))
+}
+
+/// Limit a query to documents crawled in a range of dates
+pub fn with_dates(
+    query: Box<dyn Query>,
+    after: Option<DateTime>,
+    before: Option<DateTime>,
+) -> Box<dyn Query> {
+    if after.is_none() && before.is_none() {
+        return query;
+    }
+
+    let range = RangeQuery::new_date_bounds(
+        "indexed_at".to_string(),
+        after.map_or(Bound::Unbounded, Bound::Included),
+        before.map_or(Bound::Unbounded, Bound::Excluded),
+    );
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, query),
+        (Occur::Must, Box::new(range)),
+    ]))
+}
+
+/// Limit a query to documents in a language, unless it's `all`
+pub fn with_lang(schema: &Schema, query: Box<dyn Query>, lang: &str) -> Box<dyn Query> {
+    if lang == "all" {
+        return query;
+    }
+
+    let lang = Term::from_field_text(schema.get_field("lang").unwrap(), lang);
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, query),
+        (
+            Occur::Must,
+            Box::new(TermQuery::new(lang, IndexRecordOption::Basic)),
+        ),
+    ]))
+}
+
+/// Search for a single query
+///
+/// The query is normalized first, and its embedding is generated concurrently with the BM25
+/// retrieval.
+pub async fn search(
+    st: &AppState,
+    q: String,
+    opts: SearchOptions,
+) -> Result<SearchRes, Box<dyn Error + Send + Sync>> {
+    let q = query::normalize(&q, &st.config().query);
+
+    // Spawn a future to generate an embedding for the search query
+    // and keep the join handle for later
+    let jh = {
+        let se = st.se.clone();
+        // The query syntax would only confuse the model
+        let query = query::embedding_text(&q);
+        tokio::spawn(
+            async move {
+                let st = Instant::now();
+                let embedding = se
+                    .acquire()
+                    .await
+                    .generate_embedding(query)
+                    .map_err(|err| warn!("failed to generate query embedding: {err}"))
+                    .ok();
+                (embedding, st.elapsed())
+            }
+            // Keep the request's span, so logs from the task have its ID
+            .in_current_span(),
+        )
+    };
+
+    run(st, q, opts, async move {
+        jh.await.unwrap_or_else(|err| {
+            warn!("query embedding task failed: {err}");
+            (None, Duration::default())
+        })
+    })
+    .await
+}
+
+/// Search for several queries at once
+///
+/// The query embeddings are generated in a single batch, which is much cheaper than
+/// running inference for each query separately.
+pub async fn search_batch(
+    st: &AppState,
+    queries: Vec<String>,
+    opts: SearchOptions,
+) -> Result<Vec<SearchRes>, Box<dyn Error + Send + Sync>> {
+    let config = st.config();
+    let queries: Vec<_> = queries
+        .iter()
+        .map(|q| query::normalize(q, &config.query))
+        .collect();
+
+    let embedding_st = Instant::now();
+    let embeddings = st
+        .se
+        .acquire()
+        .await
+        .generate_embeddings(queries.iter().map(|q| query::embedding_text(q)).collect())
+        .map_err(|err| err.to_string());
+    let embedding_gen_tm = embedding_st.elapsed();
+
+    let embeddings: Vec<Option<Vec<f32>>> = match embeddings {
+        Ok(embeddings) => embeddings.into_iter().map(Some).collect(),
+        Err(err) => {
+            warn!("failed to generate batch embeddings: {err}");
+            vec![None; queries.len()]
+        }
+    };
+
+    let mut results = Vec::with_capacity(queries.len());
+    for (q, embedding) in queries.into_iter().zip(embeddings) {
+        let opts = opts.clone();
+        results.push(run(st, q, opts, async move { (embedding, embedding_gen_tm) }).await?);
+    }
+
+    Ok(results)
+}
+
+/// Run the search pipeline for a query
+///
+/// `embedding` resolves to the query embedding and the time it took to generate, so
+/// callers can decide how and when it's generated.
+async fn run(
+    st: &AppState,
+    q: String,
+    opts: SearchOptions,
+    embedding: impl Future<Output = (Option<Vec<f32>>, Duration)>,
+) -> Result<SearchRes, Box<dyn Error + Send + Sync>> {
+    let AppState {
+        reader,
+        parser,
+        schema,
+        se,
+        ..
+    } = st;
+    let config = st.config();
+
+    let url_field = schema.get_field("url")?;
+    let title_field = schema.get_field("title")?;
+    let breadcrumb_field = schema.get_field("breadcrumb")?;
+    let body_field = schema.get_field("body")?;
+    let description_field = schema.get_field("description")?;
+    let page_type_field = schema.get_field("page_type")?;
+    let image_field = schema.get_field("image")?;
+    let embedding_field = schema.get_field("embedding")?;
+
+    let mut snippet_gen_tm = Duration::default();
+
+    let total_st = Instant::now();
+
+    let searcher = reader.searcher();
+
+    let parse_st = Instant::now();
+    // Search the fields operators ask for, and match the words in identifiers too (if they're
+    // in the title or body of any page)
+    let known = |word: &str| {
+        [title_field, body_field].iter().any(|field| {
+            let term = Term::from_field_text(*field, word);
+            searcher.doc_freq(&term).is_ok_and(|freq| freq > 0)
+        })
+    };
+    let expanded = query::expand_identifiers(&query::map_operators(&q), &config.query, known);
+    // Parse leniently, so unbalanced quotes and the like don't take the server down
+    let (query, parse_errors) = parser.parse_query_lenient(&expanded);
+    let mut warnings: Vec<String> = parse_errors.iter().map(|err| err.to_string()).collect();
+
+    // Only show results in the requested language and range of dates
+    let query = with_lang(schema, query, &opts.lang);
+    let mut parse = |date: &Option<String>| {
+        date.as_deref()
+            .and_then(|date| parse_date(date).map_err(|err| warnings.push(err)).ok())
+    };
+    let (after, before) = (parse(&opts.after), parse(&opts.before));
+    let query = with_dates(query, after, before);
+    let parse_tm = parse_st.elapsed();
+
+    let search_st = Instant::now();
+    let top = searcher.search(&query, &TopDocs::with_limit(20))?;
+    let search_tm = search_st.elapsed();
+
+    let mut results = Vec::new();
+
+    // Fetch documents from the search index and extract their embeddings, leaving out any
+    // that are blocked
+    let fetch_st = Instant::now();
+    let mut results_raw = Vec::with_capacity(top.len());
+    let mut docs_with_embeddings: Vec<(Vec<f32>, TantivyDocument)> = Vec::with_capacity(top.len());
+    for (bm25, doc_addr) in top {
+        let doc = searcher.doc::<TantivyDocument>(doc_addr)?;
+        let url = doc
+            .get_first(url_field)
+            .and_then(|url| url.as_str())
+            .unwrap_or_default();
+        if !config.filter.allows_str(url) {
+            continue;
+        }
+
+        let embedding = doc
+            .get_first(embedding_field)
+            .and_then(|embedding| embedding.as_bytes())
+            .ok_or("indexed document has no embedding")?;
+        // Convert the Vec<u8> storage back to Vec<f32>
+        // This is safe, as long as the input size is a multiple of 4 bytes
+        let embedding = unsafe {
+            std::slice::from_raw_parts(embedding.as_ptr() as *const f32, embedding.len() / 4)
+                .to_vec()
+        };
+
+        results_raw.push((bm25, doc_addr));
+        docs_with_embeddings.push((embedding, doc));
+    }
+    let fetch_tm = fetch_st.elapsed();
+
+    // Wait for the query embedding
+    let (embedding, embedding_gen_tm) = embedding.await;
+    let embedding = embedding.ok_or("couldn't generate an embedding for the query")?;
+
+    // Sort by cosine similarity
+    let sort_st = Instant::now();
+    let similarities = se
+        .acquire()
+        .await
+        .sort_by_similarity(embedding, docs_with_embeddings.iter().map(|x| x.0.clone()))
+        .map_err(|err| err.to_string())?;
+
+    // Fuse in the static per-source boosts, the click-through boosts, and the authority
+    // boosts, and re-sort
+    let mut scores: Vec<(usize, f32, f32, u32, f32)> = similarities
+        .into_iter()
+        .map(|(i, similarity)| {
+            let url = docs_with_embeddings[i]
+                .1
+                .get_first(url_field)
+                .and_then(|url| url.as_str())
+                .unwrap_or_default();
+            let clicks = if config.clicks.enabled {
+                st.clicks.count(&q, url)
+            } else {
+                0
+            };
+            let click_boost = 1.0 + config.clicks.weight * (clicks as f32).ln_1p();
+            let authority = st.authority.score(url);
+            // Replicas don't have the link graph, so every page would have the same authority
+            let authority_boost = if config.authority.enabled && config.replica.is_none() {
+                1.0 + config.authority.weight * authority.ln_1p()
+            } else {
+                1.0
+            };
+            let fused = similarity * config.boost_for(url) * click_boost * authority_boost;
+            (i, similarity, fused, clicks, authority)
+        })
+        .collect();
+    scores.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let top_score = scores.first().map_or(0.0, |&(_, _, fused, _, _)| fused);
+    let sort_tm = sort_st.elapsed();
+
+    // Create a snippet generator
+    let mut snippet_gen_st = Instant::now();
+    let snippet_gen = SnippetGenerator::create(&searcher, &query, body_field)?;
+    snippet_gen_tm += snippet_gen_st.elapsed();
+
+    let mut answer = None;
+
+    // Get fields we need for the top 10 results and generate a snippet relevant to the search
+    // query for each
+    for (rank, &(i, similarity, fused, clicks, authority)) in scores.iter().take(10).enumerate() {
+        let doc = &docs_with_embeddings[i].1;
+        let (bm25, doc_addr) = results_raw[i];
+
+        let url = doc
+            .get_first(url_field)
+            .and_then(|url| url.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let title = doc
+            .get_first(title_field)
+            .and_then(|title| title.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let breadcrumb = doc
+            .get_first(breadcrumb_field)
+            .and_then(|breadcrumb| breadcrumb.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        // Generate snippet for the document
+        snippet_gen_st = Instant::now();
+        let snippet = snippet_gen.snippet_from_doc(doc);
+        // Short API pages often have nothing to highlight, so fall back to their description
+        let description = doc.get_first(description_field).and_then(|d| d.as_str());
+        let (snippet, snippet_text) = match description {
+            Some(description) if snippet.fragment().trim().len() < MIN_SNIPPET_CHARS => {
+                let text = truncate(description, DESCRIPTION_MAX_CHARS);
+                (tera::escape_html(&text), text)
+            }
+            _ => (
+                sanitize::snippet_html(&snippet),
+                snippet.fragment().to_string(),
+            ),
+        };
+        snippet_gen_tm += snippet_gen_st.elapsed();
+
+        let page_type = doc
+            .get_first(page_type_field)
+            .and_then(|page_type| page_type.as_str())
+            .map(str::to_string);
+        let image = doc
+            .get_first(image_field)
+            .and_then(|image| image.as_str())
+            .map(str::to_string);
+
+        // If the top result is very close to the query, pull a longer snippet out of it
+        // so simple questions can be answered without a click
+        if rank == 0 && similarity >= ANSWER_MIN_SIMILARITY {
+            snippet_gen_st = Instant::now();
+            let mut answer_gen = SnippetGenerator::create(&searcher, &query, body_field)?;
+            answer_gen.set_max_num_chars(ANSWER_MAX_CHARS);
+            let answer_snippet = answer_gen.snippet_from_doc(doc);
+            snippet_gen_tm += snippet_gen_st.elapsed();
+
+            if !answer_snippet.fragment().is_empty() {
+                answer = Some(Answer {
+                    url: url.clone(),
+                    title: title.clone(),
+                    snippet: sanitize::snippet_html(&answer_snippet),
+                });
+            }
+        }
+
+        let explain = if opts.explain {
+            Some(Explain {
+                bm25,
+                similarity,
+                clicks,
+                authority,
+                fused,
+                query: query
+                    .explain(&searcher, doc_addr)
+                    .map(|expl| expl.to_pretty_json())
+                    .unwrap_or_else(|err| err.to_string()),
+            })
+        } else {
+            None
+        };
+
+        results.push(Res {
+            favicon: favicon::url_for(&url),
+            url,
+            breadcrumb,
+            title,
+            snippet,
+            snippet_text,
+            page_type,
+            image,
+            score: normalize_score(fused, top_score),
+            weak: normalize_score(fused, top_score) < WEAK_MATCH_SCORE,
+            explain,
+        });
+    }
+
+    // Suggest ways to narrow the query down, from words its top results have in common
+    let related = related::related(
+        &searcher,
+        title_field,
+        &q,
+        results.iter().map(|res| res.title.as_str()),
+    );
+
+    let total_tm = total_st.elapsed();
+
+    let calculation = calc::instant_answer(&q).await;
+
+    // The query itself isn't logged, but its hash can be used to group repeated searches
+    info!(
+        query_hash = %query_hash(&q),
+        results = results.len(),
+        total_ms = total_tm.as_secs_f64() * 1000.0,
+        parse_ms = parse_tm.as_secs_f64() * 1000.0,
+        search_ms = search_tm.as_secs_f64() * 1000.0,
+        fetch_ms = fetch_tm.as_secs_f64() * 1000.0,
+        embedding_ms = embedding_gen_tm.as_secs_f64() * 1000.0,
+        sort_ms = sort_tm.as_secs_f64() * 1000.0,
+        "searched in {total_tm:?}"
+    );
+
+    Ok(SearchRes {
+        query: q,
+        lang: opts.lang,
+        after: opts.after,
+        before: opts.before,
+        calculation,
+        answer,
+        warnings,
+        results,
+        related,
+        timings: opts.debug.then_some(Timings {
+            total: total_tm,
+            parse: parse_tm,
+            retrieve: search_tm,
+            fetch: fetch_tm,
+            embed: embedding_gen_tm,
+            rerank: sort_tm,
+            snippets: snippet_gen_tm,
+        }),
+    })
+}
+
+/// Hash a query for logging, so searches can be grouped without logging what was searched for
+///
+/// Click counts are saved by this hash too, so it has to stay the same across Rust versions.
+pub fn query_hash(q: &str) -> String {
+    crawldb::stable_hash(&q.trim().to_lowercase())
+}
+
+/// Cut text to at most `max` characters, marking where it was cut
+pub fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Normalize a fused score to a relevance between 0 and 1
+///
+/// The fused score is a cosine similarity scaled by the boosts, so it's already
+/// comparable between queries. If boosts push the top score (`top`) above 1, every score is
+/// scaled down by it, so the boosted results still rank in order. Negative similarities
+/// (which are rare with normalized embeddings) are clamped to 0.
+fn normalize_score(fused: f32, top: f32) -> f32 {
+    (fused / top.max(1.0)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_under_1_are_left_alone() {
+        assert_eq!(normalize_score(0.8, 0.9), 0.8);
+        assert_eq!(normalize_score(-0.1, 0.9), 0.0);
+    }
+
+    #[test]
+    fn boosted_scores_keep_their_order() {
+        let top = normalize_score(1.6, 1.6);
+        let next = normalize_score(1.2, 1.6);
+        assert_eq!(top, 1.0);
+        assert!(next < top);
+        assert_eq!(normalize_score(0.8, 1.6), 0.5);
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(
+            parse_date("2024-06-30"),
+            Ok(DateTime::from_timestamp_secs(1_719_705_600))
+        );
+        assert_eq!(
+            parse_date(" 2024-02-29 "),
+            Ok(DateTime::from_timestamp_secs(1_709_164_800))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for date in [
+            "",
+            "yesterday",
+            "2024-06",
+            "2024-13-01",
+            "2024-00-10",
+            "2023-02-29",
+            "2024-06-31",
+            "2024-06-300",
+            "2024-06-30T00:00",
+            "past_decade",
+        ] {
+            assert!(parse_date(date).is_err(), "{date} should be invalid");
+        }
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        for (name, days) in RELATIVE_DATES {
+            let before = crawler::now().into_timestamp_secs();
+            let date = parse_date(name).unwrap().into_timestamp_secs();
+            let after = crawler::now().into_timestamp_secs();
+
+            let ago = days * 24 * 60 * 60;
+            assert!((before - ago..=after - ago).contains(&date), "{name}");
+        }
+    }
+}
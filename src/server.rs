@@ -0,0 +1,220 @@
+use std::{
+    error::Error,
+    fs,
+    future::IntoFuture,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto::Builder as ConnBuilder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tokio::net::{TcpListener, UnixListener};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::Config, systemd};
+
+/// Certificate and key for serving HTTPS
+#[derive(Deserialize, Clone)]
+pub struct TlsConfig {
+    /// PEM-encoded certificate chain
+    pub cert: PathBuf,
+    /// PEM-encoded private key
+    pub key: PathBuf,
+}
+
+/// Where to listen for connections
+enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+impl Listen {
+    /// Parse a listen address
+    ///
+    /// This can be a socket address (`127.0.0.1:8080`), just a port (`8080`, on all
+    /// interfaces), or a Unix socket path prefixed with `unix:` (`unix:/run/mini-search.sock`).
+    fn parse(s: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else if let Ok(port) = s.parse::<u16>() {
+            Ok(Self::Tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))))
+        } else {
+            Ok(Self::Tcp(s.parse().map_err(|err| {
+                format!("invalid listen address `{s}`: {err}")
+            })?))
+        }
+    }
+}
+
+/// How long to wait for in-flight requests to finish when shutting down
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait for ctrl+c or (on Unix) SIGTERM
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(err) => {
+                warn!("couldn't listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+/// Call `reload` whenever SIGHUP is received (only on Unix)
+pub async fn on_reload_signal(reload: impl Fn()) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::hangup()) {
+            Ok(mut sig) => {
+                while sig.recv().await.is_some() {
+                    reload();
+                }
+            }
+            Err(err) => warn!("couldn't listen for SIGHUP: {err}"),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = reload;
+}
+
+/// Serve the app until a fatal error is encountered, or `shutdown` is triggered
+///
+/// When shutting down, new connections are refused and in-flight requests are given some
+/// time to finish.
+pub async fn serve(
+    app: Router,
+    config: &Config,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+    match (Listen::parse(&config.listen)?, &config.tls) {
+        (Listen::Tcp(addr), Some(tls)) => {
+            let rustls = RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?;
+
+            let handle = Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    if handle.listening().await.is_some() {
+                        systemd::ready();
+                    }
+                }
+            });
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown.cancelled().await;
+                    handle.graceful_shutdown(Some(DRAIN_TIMEOUT));
+                }
+            });
+
+            info!("listening on https://{addr}");
+            axum_server::bind_rustls(addr, rustls)
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+        (Listen::Tcp(addr), None) => {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening on http://{addr}");
+            systemd::ready();
+
+            let server = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+            .into_future();
+            tokio::pin!(server);
+
+            // Once shutting down, give in-flight requests some time to finish, so a slow or
+            // keep-alive client can't hold it up
+            tokio::select! {
+                res = &mut server => res?,
+                _ = shutdown.cancelled() => {
+                    match tokio::time::timeout(DRAIN_TIMEOUT, server).await {
+                        Ok(res) => res?,
+                        Err(_) => warn!("timed out waiting for connections to close"),
+                    }
+                }
+            }
+        }
+        (Listen::Unix(_), Some(_)) => {
+            return Err("TLS isn't supported when listening on a Unix socket".into());
+        }
+        (Listen::Unix(path), None) => serve_unix(app, path, shutdown).await?,
+    }
+
+    Ok(())
+}
+
+/// Serve the app on a Unix socket
+///
+/// This is for running behind a reverse proxy without exposing a TCP port. There's no
+/// client address on a Unix socket, so rate limiting should use `X-Forwarded-For`.
+async fn serve_unix(
+    app: Router,
+    path: PathBuf,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+    // Clean up the socket left behind by a previous run
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    info!("listening on unix:{}", path.display());
+    systemd::ready();
+
+    let builder = ConnBuilder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+
+    loop {
+        let socket = tokio::select! {
+            res = listener.accept() => res?.0,
+            _ = shutdown.cancelled() => break,
+        };
+        let svc = TowerToHyperService::new(app.clone());
+
+        let conn = builder
+            .serve_connection_with_upgrades(TokioIo::new(socket), svc)
+            .into_owned();
+        let conn = graceful.watch(conn);
+
+        tokio::spawn(async move {
+            if let Err(err) = conn.await {
+                debug!("failed to serve connection: {err}");
+            }
+        });
+    }
+
+    if tokio::time::timeout(DRAIN_TIMEOUT, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        warn!("timed out waiting for connections to close");
+    }
+    let _ = fs::remove_file(&path);
+
+    Ok(())
+}
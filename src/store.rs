@@ -0,0 +1,88 @@
+use std::{
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::index::INDEX_DIR;
+
+/// Small piece of state kept in a JSON file next to the search index
+///
+/// This is for things like saved pages, which are small enough to keep in memory and rewrite
+/// whenever they change. Things that change on every search, like history, are changed in memory
+/// and saved every so often instead.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: Mutex<T>,
+    /// Whether there are changes that haven't been saved
+    dirty: AtomicBool,
+}
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    /// Load `name` from the index directory (or start with the default, if it doesn't exist)
+    pub fn load(name: &str) -> Result<Self, Box<dyn Error>> {
+        let path = Path::new(INDEX_DIR).join(name);
+        let data = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => T::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path,
+            data: Mutex::new(data),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Read the data
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.lock().unwrap())
+    }
+
+    /// Change the data and save it right away
+    pub fn update<R>(
+        &self,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, Box<dyn Error + Send + Sync>> {
+        let mut data = self.data.lock().unwrap();
+        let res = f(&mut data);
+        self.write(&data)?;
+        self.dirty.store(false, Ordering::Relaxed);
+
+        Ok(res)
+    }
+
+    /// Change the data without saving it, for [JsonStore::save] to save later
+    pub fn change<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let res = f(&mut self.data.lock().unwrap());
+        self.dirty.store(true, Ordering::Relaxed);
+        res
+    }
+
+    /// Save the data, if anything changed since it was last saved
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let data = self.data.lock().unwrap();
+        self.write(&data)
+            .inspect_err(|_| self.dirty.store(true, Ordering::Relaxed))
+    }
+
+    fn write(&self, data: &T) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Write to a temporary file first, so a crash can't leave a half-written file behind
+        let part = self.path.with_extension("json.part");
+        fs::write(&part, serde_json::to_vec(data)?)?;
+        fs::rename(&part, &self.path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, Occur, Query, RegexQuery, TermQuery},
+    schema::{IndexRecordOption, Value},
+    TantivyDocument, Term,
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{search, AppState};
+
+/// Most suggestions returned for a query
+const MAX_SUGGESTIONS: usize = 8;
+
+/// Suggest page titles completing a partially typed query
+///
+/// Every complete word has to be in the title, and the last word (unless it's followed by a
+/// space) only has to be the start of one.
+pub fn suggest(st: &AppState, q: &str, lang: &str) -> tantivy::Result<Vec<String>> {
+    let title = st.schema.get_field("title")?;
+    let url = st.schema.get_field("url")?;
+
+    // Split words the same way the default tokenizer does, so they match indexed terms
+    let q: String = q.nfc().collect();
+    let mut words: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let prefix = if q.ends_with(char::is_alphanumeric) {
+        words.pop()
+    } else {
+        None
+    };
+    if words.is_empty() && prefix.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = words
+        .iter()
+        .map(|w| {
+            let term = Term::from_field_text(title, w);
+            let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+            (Occur::Must, query)
+        })
+        .collect();
+    if let Some(prefix) = prefix {
+        // The prefix is only alphanumeric, so it doesn't need escaping
+        clauses.push((
+            Occur::Must,
+            Box::new(RegexQuery::from_pattern(&format!("{prefix}.*"), title)?),
+        ));
+    }
+    let query = search::with_lang(&st.schema, Box::new(BooleanQuery::new(clauses)), lang);
+
+    let config = st.config();
+    let searcher = st.reader.searcher();
+    let top = searcher.search(&query, &TopDocs::with_limit(MAX_SUGGESTIONS * 4))?;
+
+    // Many pages share a title, so skip the duplicates
+    let mut seen = HashSet::new();
+    let mut suggestions = Vec::new();
+    for (_, doc_addr) in top {
+        let doc = searcher.doc::<TantivyDocument>(doc_addr)?;
+        let Some(title) = doc.get_first(title).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let url = doc
+            .get_first(url)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        if !config.filter.allows_str(url) {
+            continue;
+        }
+
+        if seen.insert(title.to_lowercase()) {
+            suggestions.push(title.to_string());
+            if suggestions.len() == MAX_SUGGESTIONS {
+                break;
+            }
+        }
+    }
+
+    Ok(suggestions)
+}
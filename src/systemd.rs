@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tokio_util::sync::CancellationToken;
+
+/// Tell systemd we're ready to serve requests
+///
+/// This (like the rest of this module) does nothing when we aren't running under systemd.
+pub fn ready() {
+    notify(&[NotifyState::Ready]);
+}
+
+/// Tell systemd we're shutting down
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(err) = sd_notify::notify(false, state) {
+        warn!("couldn't notify systemd: {err}");
+    }
+}
+
+/// Ping the watchdog in the background, if systemd has it enabled
+///
+/// The pings come from a task on the async runtime, so if the runtime hangs, systemd notices
+/// and restarts us.
+pub fn spawn_watchdog(shutdown: CancellationToken) {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+
+    // Ping twice per timeout, so one late ping doesn't get us killed
+    let period = Duration::from_micros(usec) / 2;
+    info!("pinging the systemd watchdog every {period:?}");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => notify(&[NotifyState::Watchdog]),
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,62 @@
+use std::path::Path as FsPath;
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+use tera::Tera;
+
+/// HTML templates compiled into the binary
+#[derive(RustEmbed)]
+#[folder = "views/"]
+struct Views;
+
+/// Static assets compiled into the binary
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+/// Load the HTML templates
+///
+/// Templates in `views/` on disk override the ones compiled into the binary, so they can be
+/// customized without rebuilding.
+pub fn load() -> Result<Tera, tera::Error> {
+    if FsPath::new("views").is_dir() {
+        info!("loading templates from views/");
+        return Tera::new("views/*");
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(
+        Views::iter()
+            .filter_map(|name| {
+                let file = Views::get(&name)?;
+                Some((
+                    name.to_string(),
+                    String::from_utf8_lossy(&file.data).into_owned(),
+                ))
+            })
+            .collect::<Vec<_>>(),
+    )?;
+
+    Ok(tera)
+}
+
+/// Whether static assets should be served from `static/` on disk instead of the binary
+pub fn assets_on_disk() -> bool {
+    FsPath::new("static").is_dir()
+}
+
+/// `GET /static/*path`: serve a static asset compiled into the binary
+pub async fn asset(Path(path): Path<String>) -> Response {
+    match Assets::get(&path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,89 @@
+use regex::Regex;
+
+/// A regex replacement applied to page titles when they're indexed
+///
+/// This is for stripping boilerplate, like the ` - Rust` at the end of every rustdoc page, so
+/// results read cleanly and title embeddings are about what the page is actually about.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "TitleRuleConfig", into = "TitleRuleConfig")]
+pub struct TitleRule {
+    /// Only apply this to pages from this source (or every page, if unset)
+    source: Option<String>,
+    pattern: Regex,
+    replace: String,
+}
+
+/// A title rule as it's written in the config
+#[derive(Serialize, Deserialize)]
+struct TitleRuleConfig {
+    source: Option<String>,
+    pattern: String,
+    #[serde(default)]
+    replace: String,
+}
+
+impl TryFrom<TitleRuleConfig> for TitleRule {
+    type Error = regex::Error;
+
+    fn try_from(config: TitleRuleConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            source: config.source,
+            pattern: Regex::new(&config.pattern)?,
+            replace: config.replace,
+        })
+    }
+}
+
+impl From<TitleRule> for TitleRuleConfig {
+    fn from(rule: TitleRule) -> Self {
+        Self {
+            source: rule.source,
+            pattern: rule.pattern.as_str().to_string(),
+            replace: rule.replace,
+        }
+    }
+}
+
+impl TitleRule {
+    fn new(source: &str, pattern: &str) -> Self {
+        Self {
+            source: Some(source.to_string()),
+            pattern: Regex::new(pattern).unwrap(),
+            replace: String::new(),
+        }
+    }
+}
+
+/// The title rules used when the config doesn't list any, for the default crawl targets
+pub fn default_rules() -> Vec<TitleRule> {
+    vec![
+        TitleRule::new("doc.rust-lang.org", r" - Rust$"),
+        TitleRule::new("docs.rs", r" - Rust$"),
+        TitleRule::new("docs.python.org", r" [—-] Python [0-9.]+ documentation$"),
+        TitleRule::new(
+            "docs.ruby-lang.org",
+            r" - (Documentation for )?Ruby [0-9.]+$",
+        ),
+    ]
+}
+
+/// Apply the rules for a page's source to its title, in order
+///
+/// If the rules would leave nothing, the original title is kept.
+pub fn clean(rules: &[TitleRule], source: &str, title: &str) -> String {
+    let cleaned = rules
+        .iter()
+        .filter(|rule| rule.source.as_deref().map_or(true, |s| s == source))
+        .fold(title.to_string(), |title, rule| {
+            rule.pattern
+                .replace_all(&title, rule.replace.as_str())
+                .into_owned()
+        });
+
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() {
+        title.to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
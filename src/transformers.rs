@@ -1,9 +1,16 @@
-use std::{error::Error, fs::File, io::Read};
+use std::{
+    error::Error,
+    fs::File,
+    io::Read,
+    ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use candle_core::{DType, Device, Shape, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::bert::{self, BertModel};
 use tokenizers::{PaddingParams, Tokenizer};
+use tokio::sync::{Semaphore, SemaphorePermit};
 
 // This code is pretty heavily based on the candle example:
 // <https://github.com/huggingface/candle/blob/530ab96036604b125276433b67ebb840e841aede/candle-examples/examples/bert/main.rs#L146C9-L205C10>
@@ -12,6 +19,8 @@ use tokenizers::{PaddingParams, Tokenizer};
 pub struct SentEmbed {
     tokenizer: Tokenizer,
     bert: BertModel,
+    /// Length of each embedding
+    dim: usize,
 }
 impl SentEmbed {
     pub fn new() -> Result<Self, Box<dyn Error>> {
@@ -43,17 +52,26 @@ impl SentEmbed {
             tokenizer.with_padding(Some(pp));
         }
 
-        Ok(Self { tokenizer, bert })
+        Ok(Self {
+            tokenizer,
+            bert,
+            dim: config.hidden_size,
+        })
+    }
+
+    /// Length of the embeddings this generates
+    pub fn dim(&self) -> usize {
+        self.dim
     }
 
     /// Generate an embedding for the given sentence
     ///
     /// This returns a raw tensor without any conversions.
-    fn gen_embedding(&mut self, sentence: String) -> Result<Tensor, Box<dyn Error>> {
+    fn gen_embedding(&self, sentence: String) -> Result<Tensor, Box<dyn Error>> {
         let tokens = self
             .tokenizer
             .encode(vec![sentence], true)
-            .expect("aaaaaaaaaaa");
+            .map_err(|err| err.to_string())?;
 
         let embeddings = self.run_inference(&[tokens])?;
 
@@ -63,26 +81,38 @@ impl SentEmbed {
     /// Generate an embedding for the given sentence
     ///
     /// This converts the embedding to a [Vec] internally.
-    pub fn generate_embedding(&mut self, sentence: String) -> Result<Vec<f32>, Box<dyn Error>> {
+    pub fn generate_embedding(&self, sentence: String) -> Result<Vec<f32>, Box<dyn Error>> {
         Ok(self.gen_embedding(sentence)?.to_vec1()?)
     }
 
+    /// Generate embeddings for several sentences in one batch
+    pub fn generate_embeddings(
+        &self,
+        sentences: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+        // Encode each sentence the same way `gen_embedding` does, so the embeddings match
+        let tokens = self
+            .tokenizer
+            .encode_batch(
+                sentences.into_iter().map(|s| vec![s]).collect::<Vec<_>>(),
+                true,
+            )
+            .map_err(|err| err.to_string())?;
+
+        Ok(self.run_inference(&tokens)?.to_vec2()?)
+    }
+
     /// Run inference on some tokens
     fn run_inference(&self, tokens: &[tokenizers::Encoding]) -> Result<Tensor, Box<dyn Error>> {
         let token_ids = tokens
             .iter()
-            .map(|tokens| {
-                Tensor::new(tokens.get_ids(), &self.bert.device).expect("something's pretty wrong")
-            })
-            .collect::<Vec<_>>();
+            .map(|tokens| Tensor::new(tokens.get_ids(), &self.bert.device))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let attention_mask = tokens
             .iter()
-            .map(|tokens| {
-                Tensor::new(tokens.get_attention_mask(), &self.bert.device)
-                    .expect("something's pretty wrong")
-            })
-            .collect::<Vec<_>>();
+            .map(|tokens| Tensor::new(tokens.get_attention_mask(), &self.bert.device))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let token_ids = Tensor::stack(&token_ids, 0)?;
         let attention_mask = Tensor::stack(&attention_mask, 0)?;
@@ -92,9 +122,11 @@ impl SentEmbed {
             .bert
             .forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
 
-        // Apply some avg-pooling by taking the mean embedding value for all tokens (including padding)
-        let (_n_sentence, n_tokens, _hidden_size) = embeddings.dims3()?;
-        let embeddings = (embeddings.sum(1)? / (n_tokens as f64))?;
+        // Apply some avg-pooling by taking the mean embedding value for all tokens,
+        // masking out padding so batched sentences get the same embedding as single ones
+        let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let embeddings = embeddings.broadcast_mul(&mask)?.sum(1)?;
+        let embeddings = embeddings.broadcast_div(&mask.sum(1)?)?;
         let embeddings = normalize_l2(&embeddings)?;
 
         Ok(embeddings)
@@ -102,19 +134,22 @@ impl SentEmbed {
 
     /// Sort a set of candidates by their similarity to the given query
     pub fn sort_by_similarity(
-        &mut self,
+        &self,
         query: Vec<f32>,
         candidates: impl Iterator<Item = Vec<f32>>,
     ) -> Result<Vec<(usize, f32)>, Box<dyn Error>> {
-        let qe = Tensor::from_vec(query, Shape::from_dims(&[384]), &self.bert.device)?;
+        let qe = Tensor::from_vec(query, Shape::from_dims(&[self.dim]), &self.bert.device)?;
 
         // Calculate cosine similarities for each candidate
         let mut similarities = candidates
             .enumerate()
             .map(|(i, candidate)| {
                 // Rebuild a tensor for candidate embedding
-                let ce =
-                    Tensor::from_slice(&candidate, Shape::from_dims(&[384]), &self.bert.device)?;
+                let ce = Tensor::from_slice(
+                    &candidate,
+                    Shape::from_dims(&[self.dim]),
+                    &self.bert.device,
+                )?;
 
                 // Calculate sum of elements for q*c, q^2, and c^2
                 let sum_qc = (&qe * &ce)?.sum_all()?.to_scalar::<f32>()?;
@@ -134,6 +169,68 @@ impl SentEmbed {
     }
 }
 
+/// The embedding model, shared by searches, crawls, and the indexing API
+///
+/// At most `max_concurrent` inferences run at once. They all share the model's weights, so
+/// each one only costs the memory for its activations, which grows with the batch size.
+pub struct Embedder {
+    model: SentEmbed,
+    permits: Semaphore,
+    /// Whether the model ran inference when it was warmed up
+    ready: AtomicBool,
+}
+impl Embedder {
+    pub fn new(model: SentEmbed, max_concurrent: usize) -> Self {
+        Self {
+            model,
+            permits: Semaphore::new(max_concurrent.max(1)),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Run inference once, to check the model works (and so the first search isn't slow)
+    ///
+    /// This blocks until it's done, so run it before serving requests.
+    pub fn warm_up(&self) {
+        match self.model.generate_embedding("ready".to_string()) {
+            Ok(_) => self.ready.store(true, Ordering::Relaxed),
+            Err(err) => error!("the embedding model couldn't run inference: {err}"),
+        }
+    }
+
+    /// Whether the model ran inference when it was warmed up
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Length of the embeddings the model generates
+    pub fn dim(&self) -> usize {
+        self.model.dim()
+    }
+
+    /// Wait for a turn to use the model
+    pub async fn acquire(&self) -> EmbedderGuard<'_> {
+        EmbedderGuard {
+            model: &self.model,
+            // The semaphore is never closed
+            _permit: self.permits.acquire().await.unwrap(),
+        }
+    }
+}
+
+/// A turn to use the model, which ends when this is dropped
+pub struct EmbedderGuard<'a> {
+    model: &'a SentEmbed,
+    _permit: SemaphorePermit<'a>,
+}
+impl Deref for EmbedderGuard<'_> {
+    type Target = SentEmbed;
+
+    fn deref(&self) -> &SentEmbed {
+        self.model
+    }
+}
+
 // <https://github.com/huggingface/candle/blob/530ab96036604b125276433b67ebb840e841aede/candle-examples/examples/bert/main.rs#L210C1-L212C2>
 pub fn normalize_l2(v: &Tensor) -> Result<Tensor, Box<dyn Error>> {
     Ok(v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?)?)
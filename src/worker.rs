@@ -0,0 +1,224 @@
+use std::{error::Error, time::Duration};
+
+use spider::{
+    reqwest::{header, Client, StatusCode},
+    url::Url,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    crawldb::{FailedFetch, Fetch},
+    crawler,
+    extract::Extractors,
+    ingest::DocumentReq,
+    queue::{Lease, WorkerPages},
+    title, Config, SentEmbed,
+};
+
+/// How long to wait before asking again when the queue is empty
+const IDLE_WAIT: Duration = Duration::from_secs(30);
+
+/// Run as a crawl worker for the primary at `primary`, until `shutdown` is cancelled
+///
+/// The worker takes targets from the primary's queue, crawls them, and sends the pages
+/// (with their embeddings) back to be indexed, along with which pages couldn't be fetched. `key` is one of the primary's admin keys.
+/// The worker's own `config` decides how it crawls and how much memory it uses.
+pub async fn run_worker(
+    primary: Url,
+    key: String,
+    extractors: Extractors,
+    config: Config,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+    let se = SentEmbed::new()?;
+    let client = Client::builder().timeout(Duration::from_secs(60)).build()?;
+    let worker = Worker {
+        primary,
+        key,
+        client,
+    };
+
+    info!(primary = %worker.primary, "crawl worker started");
+
+    while !shutdown.is_cancelled() {
+        let lease = match worker.lease().await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => {
+                debug!("the queue is empty, waiting");
+                tokio::select! {
+                    _ = tokio::time::sleep(IDLE_WAIT) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+                continue;
+            }
+            Err(err) => {
+                error!("couldn't get a target from the primary: {err}");
+                tokio::select! {
+                    _ = tokio::time::sleep(IDLE_WAIT) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+                continue;
+            }
+        };
+
+        let target = &lease.target;
+        info!(id = lease.id, source = %target.source, url = %target.url, "crawling target");
+
+        // If this fails, the lease runs out and the target is given to another worker
+        match worker
+            .crawl(&lease, &extractors, &se, &config, &shutdown)
+            .await
+        {
+            Ok(pages) if !shutdown.is_cancelled() => {
+                info!(id = lease.id, url = %target.url, pages, "finished crawling target");
+                if let Err(err) = worker.finish(lease.id).await {
+                    error!("couldn't report target {} as done: {err}", lease.id);
+                }
+            }
+            Ok(_) => info!(
+                id = lease.id,
+                "crawl interrupted, leaving the target to expire"
+            ),
+            Err(err) => error!(id = lease.id, url = %target.url, "crawl failed: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Connection to the primary
+struct Worker {
+    primary: Url,
+    key: String,
+    client: Client,
+}
+impl Worker {
+    fn url(&self, path: &str) -> Result<Url, Box<dyn Error>> {
+        Ok(self.primary.join(path)?)
+    }
+
+    /// Take the next target from the queue
+    async fn lease(&self) -> Result<Option<Lease>, Box<dyn Error>> {
+        let res = self
+            .client
+            .post(self.url("admin/queue/lease")?)
+            .bearer_auth(&self.key)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        if res.status() == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&res.bytes().await?)?))
+    }
+
+    /// Report a target as done
+    async fn finish(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.client
+            .post(self.url(&format!("admin/queue/{id}/done"))?)
+            .bearer_auth(&self.key)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Send pages crawled for a lease to the primary
+    async fn send(&self, id: u64, pages: &WorkerPages) -> Result<(), Box<dyn Error>> {
+        self.client
+            .post(self.url(&format!("admin/queue/{id}/pages"))?)
+            .bearer_auth(&self.key)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(pages)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Crawl a leased target, sending its pages to the primary, and return how many were sent
+    async fn crawl(
+        &self,
+        lease: &Lease,
+        extractors: &Extractors,
+        se: &SentEmbed,
+        config: &Config,
+        shutdown: &CancellationToken,
+    ) -> Result<usize, Box<dyn Error>> {
+        let target = &lease.target;
+        let (pages, failed): (Vec<_>, Vec<_>) = crawler::scrape(target, &config.crawler, shutdown)
+            .await
+            .into_iter()
+            .partition(|page| page.ok());
+        // Failures go along with the first batch of pages
+        let mut failed: Vec<_> = failed
+            .iter()
+            .map(|page| FailedFetch {
+                url: page.url.to_string(),
+                error: page.error(),
+            })
+            .collect();
+
+        let mut sent = 0;
+        for batch in pages.chunks(config.memory.embedding_batch.max(1)) {
+            if shutdown.is_cancelled() {
+                break;
+            }
+
+            let extracted: Vec<_> = batch
+                .iter()
+                .map(|scraped| {
+                    let mut page = extractors.extract(&scraped.url, &scraped.html);
+                    page.title = title::clean(&lease.title_rules, &target.source, &page.title);
+                    (&scraped.url, page)
+                })
+                .collect();
+            let embeddings = se.generate_embeddings(
+                extracted
+                    .iter()
+                    .map(|(_, page)| page.title.clone())
+                    .collect(),
+            )?;
+
+            let docs: Vec<_> = extracted
+                .into_iter()
+                .zip(embeddings)
+                .map(|((url, page), embedding)| DocumentReq {
+                    url: url.to_string(),
+                    description: page.description().map(str::to_string),
+                    page_type: page.page_type().map(str::to_string),
+                    image: page.image().map(str::to_string),
+                    title: page.title,
+                    body: page.body,
+                    source: Some(target.source.clone()),
+                    lang: Some(page.lang),
+                    embedding: Some(embedding),
+                    links: page.links,
+                })
+                .collect();
+
+            let count = docs.len();
+            let pages = WorkerPages {
+                docs,
+                fetched: batch.iter().map(Fetch::new).collect(),
+                failed: std::mem::take(&mut failed),
+            };
+            self.send(lease.id, &pages).await?;
+
+            sent += count;
+            debug!(id = lease.id, sent, "sent pages to the primary");
+        }
+        if !failed.is_empty() && !shutdown.is_cancelled() {
+            let pages = WorkerPages {
+                failed,
+                ..Default::default()
+            };
+            self.send(lease.id, &pages).await?;
+        }
+
+        Ok(sent)
+    }
+}